@@ -0,0 +1,405 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use log::info;
+use macaw::{UVec2, uvec2, Vec2};
+use crate::camera::CameraConfig;
+use crate::emitter::{self, Emitter};
+use crate::export::{Export, ExportSettings, VideoFormat};
+use crate::lighting::Lighting;
+use crate::raindrops::Raindrops;
+use crate::record::{self, InputEvent, Recording, Replay};
+use crate::render_target::Frame;
+use crate::renderer::{self, GfxState};
+use crate::sim_renderer::{RenderConfig, RenderMode};
+use crate::simulation::{self, WaveSimulation};
+use crate::scene::Scene;
+use crate::snapshot::Snapshot;
+use crate::solver::{self, SolverKind, WaveSolver};
+
+pub const USAGE: &str = "usage: ripple batch [--output SNAPSHOT] [--png IMAGE] [--scene SCENE]
+                    [--load SNAPSHOT | --replay RECORDING]
+                    [--ticks N] [--size WxH] [--solver cpu|gpu] [--seed N] [--raindrops DELAY]
+                    [--render texture|prism] [--image-size WxH] [--software]
+                    [--frames N [--frames-dir DIR] [--video FILE.y4m|FILE.gif] [--ticks-per-frame N]
+                     [--fps N] [--no-orbit]]";
+
+pub const EXPORT_USAGE: &str = "usage: ripple export [--frames N] [--frames-dir DIR] [--video FILE.y4m|FILE.gif]
+                     [--ticks-per-frame N] [--fps N] [--no-orbit] [--image-size WxH]
+                     [--scene SCENE] [--load SNAPSHOT | --replay RECORDING] [--ticks N]
+                     [--size WxH] [--solver cpu|gpu] [--seed N] [--raindrops DELAY]
+                     [--render texture|prism] [--software]
+
+Same as ripple batch, but always renders a clip, 300 frames unless --frames is given";
+
+/// Runs the simulation without a window and writes the final field to a snapshot, an image or
+/// a clip of the run
+pub struct BatchOptions {
+    output: Option<PathBuf>,
+    /// Draws the final field and saves it as a PNG
+    png: Option<PathBuf>,
+    /// Renders frames after the first `ticks`, see `ExportSettings`
+    export: Option<ExportSettings>,
+    /// Sets the run up before any snapshot is loaded. Only the render settings and camera are
+    /// used when replaying.
+    scene: Option<PathBuf>,
+    load: Option<PathBuf>,
+    /// Plays a recording back and checks the result against it
+    replay: Option<PathBuf>,
+    /// Defaults to the end of the replay, if there is one, or none at all before exporting
+    ticks: Option<u64>,
+    /// Resamples the scene's field to this size when both are given. A loaded snapshot or a
+    /// replay keeps its own size.
+    size: Option<UVec2>,
+    solver: SolverKind,
+    seed: u64,
+    /// Ticks between raindrops, no rain if not given unless the scene has it
+    raindrop_delay: Option<u32>,
+    render_mode: RenderMode,
+    image_size: UVec2,
+    /// Asks wgpu for a software adapter, for machines without a GPU
+    software: bool,
+}
+
+impl BatchOptions {
+    pub fn from_args(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        Self::parse_args(args, false)
+    }
+
+    /// For `ripple export`, which is a batch run that always renders a clip
+    pub fn from_export_args(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        Self::parse_args(args, true)
+    }
+
+    fn parse_args(mut args: impl Iterator<Item = String>, always_export: bool) -> Result<Self, String> {
+        let mut export = ExportSettings::default();
+        let mut frames = always_export.then_some(export.frames);
+        let mut options = BatchOptions {
+            output: None,
+            png: None,
+            export: None,
+            scene: None,
+            load: None,
+            replay: None,
+            ticks: None,
+            size: None,
+            solver: SolverKind::Cpu,
+            seed: 0,
+            raindrop_delay: None,
+            render_mode: RenderMode::Prism,
+            image_size: uvec2(1280, 720),
+            software: false,
+        };
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--help" | "-h" if always_export => {
+                    println!("Renders a clip of the simulation without a window\n{}", EXPORT_USAGE);
+                    std::process::exit(0);
+                }
+                "--help" | "-h" => {
+                    println!("Runs the simulation without a window\n{}", USAGE);
+                    std::process::exit(0);
+                }
+                "--output" => options.output = Some(value()?.into()),
+                "--png" => options.png = Some(value()?.into()),
+                "--scene" => options.scene = Some(value()?.into()),
+                "--load" => options.load = Some(value()?.into()),
+                "--replay" => options.replay = Some(value()?.into()),
+                "--ticks" => options.ticks = Some(parse(&arg, &value()?)?),
+                "--size" => options.size = Some(parse_sim_size(&value()?)?),
+                "--solver" => {
+                    let name = value()?;
+                    options.solver = SolverKind::from_name(&name)
+                        .ok_or_else(|| format!("unknown solver {}", name))?;
+                }
+                "--seed" => options.seed = parse(&arg, &value()?)?,
+                "--raindrops" => options.raindrop_delay = Some(parse(&arg, &value()?)?),
+                "--render" => {
+                    let name = value()?;
+                    options.render_mode = RenderMode::from_name(&name)
+                        .ok_or_else(|| format!("unknown render mode {}", name))?;
+                }
+                "--image-size" => options.image_size = parse_size(&value()?)?,
+                "--software" => options.software = true,
+                "--frames" => frames = Some(parse(&arg, &value()?)?),
+                "--frames-dir" => export.directory = value()?.into(),
+                "--video" => export.video = Some(value()?.into()),
+                "--ticks-per-frame" => export.ticks_per_frame = parse(&arg, &value()?)?,
+                "--fps" => export.fps = parse(&arg, &value()?)?,
+                "--no-orbit" => export.orbit = false,
+                _ => return Err(format!("unexpected argument {}", arg)),
+            }
+        }
+        if let Some(frames) = frames {
+            if frames == 0 || export.fps == 0 {
+                return Err("--frames and --fps have to be at least 1".into());
+            }
+            if export.video.as_deref().is_some_and(|path| VideoFormat::from_path(path).is_none()) {
+                return Err("--video has to end in .y4m or .gif".into());
+            }
+            options.export = Some(ExportSettings {
+                frames,
+                size: options.image_size,
+                ..export
+            });
+        }
+        if options.output.is_none() && options.png.is_none() && options.export.is_none() {
+            return Err("--output, --png or --frames is required".into());
+        }
+        if options.load.is_some() && options.replay.is_some() {
+            return Err("--load and --replay can't be used together".into());
+        }
+        if options.ticks.is_none() && options.replay.is_none() && options.export.is_none() {
+            return Err("--ticks is required unless replaying or exporting".into());
+        }
+        Ok(options)
+    }
+}
+
+fn parse<T: std::str::FromStr>(arg: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("bad value {} for {}", value, arg))
+}
+
+/// Parses sizes like `256x128`
+pub fn parse_size(value: &str) -> Result<UVec2, String> {
+    let error = || format!("bad size {}, expected WIDTHxHEIGHT", value);
+    let (width, height) = value.split_once('x').ok_or_else(error)?;
+    let size = uvec2(width.parse().map_err(|_| error())?, height.parse().map_err(|_| error())?);
+    if size.min_element() == 0 {
+        return Err(error());
+    }
+    Ok(size)
+}
+
+/// `parse_size` for simulation grids, which are held to `simulation::MAX_SIZE`
+pub fn parse_sim_size(value: &str) -> Result<UVec2, String> {
+    let size = parse_size(value)?;
+    simulation::check_size(size)?;
+    Ok(size)
+}
+
+struct Batch {
+    solver: Box<dyn WaveSolver>,
+    /// Only created when something asks for the GPU solver or an image
+    gpu: Option<(Arc<wgpu::Device>, Arc<wgpu::Queue>)>,
+    software: bool,
+    /// Created on the first `draw`, on the same device as the GPU solver
+    renderer: Option<GfxState<'static>>,
+    grid_extent: Vec2,
+    render_config: RenderConfig,
+    camera_config: CameraConfig,
+    lighting: Lighting,
+    render_mode: RenderMode,
+    image_size: UVec2,
+    tick: u64,
+    emitters: Vec<Emitter>,
+    raindrops: Option<Raindrops>,
+    replay: Option<Replay>,
+}
+
+impl Batch {
+    fn gpu(&mut self) -> Result<(Arc<wgpu::Device>, Arc<wgpu::Queue>), String> {
+        if self.gpu.is_none() {
+            self.gpu = pollster::block_on(renderer::request_headless_device(self.software));
+        }
+        self.gpu.clone().ok_or_else(|| "no GPU adapter available".into())
+    }
+
+    fn set_solver(&mut self, kind: SolverKind) -> Result<(), String> {
+        if self.solver.kind() != kind {
+            let (device, queue) = self.gpu()?;
+            self.solver = solver::switch_solver(self.solver.as_ref(), kind, &device, &queue);
+        }
+        Ok(())
+    }
+
+    /// Same as `App::apply_event`, minus everything to do with rendering
+    fn apply_event(&mut self, event: InputEvent) -> Result<(), String> {
+        match event {
+            InputEvent::Poke { poke, center } => self.solver.poke(&poke, center),
+            InputEvent::Paint { brush, center } => self.solver.paint(&brush, center),
+            InputEvent::ClearMedium => self.solver.clear_medium(),
+            InputEvent::Reset => self.solver.reset(),
+            InputEvent::Resize(size) => self.solver.resize(size),
+            InputEvent::SetSolver(kind) => self.set_solver(kind)?,
+            InputEvent::Settings(settings) => {
+                *self.solver.params_mut() = settings.params;
+                self.emitters = settings.emitters;
+            }
+        }
+        Ok(())
+    }
+
+    /// Same as `App::cells_per_world_unit`, raindrops are sized the same as in the window
+    fn cells_per_world_unit(&self) -> f32 {
+        self.solver.size().x as f32 / self.grid_extent.x
+    }
+
+    fn run(&mut self, ticks: u64) -> Result<(), String> {
+        let end = self.tick.saturating_add(ticks);
+        // Only replays resize the grid, and they don't rain
+        let cells_per_world_unit = self.cells_per_world_unit();
+        while self.tick < end {
+            if let Some(mut replay) = self.replay.take() {
+                let running = replay.step(self.tick, |event| self.apply_event(event));
+                self.replay = Some(replay);
+                if !running? {
+                    break;
+                }
+            } else if let Some(raindrops) = &mut self.raindrops {
+                if let Some(position) = raindrops.tick() {
+                    let poke = raindrops.settings.poke.poke(cells_per_world_unit);
+                    self.solver.poke(&poke, position * self.solver.size().as_vec2());
+                }
+            }
+            let sources = emitter::sources(&self.emitters, self.tick, self.solver.size());
+            self.solver.set_sources(&sources);
+            self.solver.advance();
+            self.tick += 1;
+        }
+        Ok(())
+    }
+
+    /// Draws the field the way the window would, with the camera turned `rotation` radians
+    /// from its starting point
+    fn draw(&mut self, rotation: f32) -> Result<Frame, String> {
+        let sim_size = self.solver.size();
+        if self.renderer.is_none() {
+            let (device, queue) = self.gpu()?;
+            let (mesh, grid) = self.render_config.build(sim_size);
+            let mut renderer = GfxState::new_offscreen(
+                device, queue, self.image_size, 60f32.to_radians(), &mesh, &grid, sim_size,
+            );
+            renderer.sim.render_mode = self.render_mode;
+            self.grid_extent = grid.extent;
+            self.renderer = Some(renderer);
+        }
+        let renderer = self.renderer.as_mut().unwrap();
+        if renderer.sim.sim_size() != sim_size {
+            // A replay resized the grid
+            let (mesh, grid) = self.render_config.build(sim_size);
+            renderer.sim.resize_sim(&renderer.device, sim_size);
+            renderer.sim.update_prism(&renderer.device, &mesh);
+            renderer.sim.update_grid(&renderer.device, &grid);
+            self.grid_extent = grid.extent;
+        }
+        renderer.set_camera(&self.camera_config.orbit(rotation, self.grid_extent));
+        renderer.set_lighting(&self.lighting, self.grid_extent, self.render_config.prism_height);
+        renderer.update_field(self.solver.output(), 1.0);
+        renderer.render().map_err(|error| error.to_string())?;
+        Ok(renderer.read_frame().unwrap())
+    }
+}
+
+pub fn run(options: BatchOptions) -> Result<(), String> {
+    let mut batch = Batch {
+        solver: Box::new(WaveSimulation::new(options.size.unwrap_or(simulation::DEFAULT_SIZE))),
+        gpu: None,
+        software: options.software,
+        renderer: None,
+        grid_extent: Vec2::ONE,
+        render_config: RenderConfig::default(),
+        camera_config: CameraConfig::default(),
+        lighting: Lighting::default(),
+        render_mode: options.render_mode,
+        image_size: options.image_size,
+        tick: 0,
+        emitters: Vec::new(),
+        raindrops: None,
+        replay: None,
+    };
+
+    let mut scene = None;
+    if let Some(path) = &options.scene {
+        scene = Some(Scene::load(path).map_err(|error| format!("{}: {}", path.display(), error))?);
+    }
+    let mut start = None;
+    if let Some(path) = &options.load {
+        start = Some(Snapshot::load(path).map_err(|error| format!("{}: {}", path.display(), error))?);
+    }
+    if let Some(path) = &options.replay {
+        let recording = Recording::load(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        batch.set_solver(recording.solver)?;
+        batch.solver.resize(recording.start.size);
+        recording.start.restore(batch.solver.as_mut());
+        batch.tick = recording.start.tick;
+        batch.replay = Some(Replay::new(recording));
+    } else {
+        batch.set_solver(options.solver)?;
+        if let Some(scene) = &scene {
+            scene.apply_field(batch.solver.as_mut());
+            batch.emitters = scene.emitters.clone();
+            if scene.raindrops.enabled {
+                let mut raindrops = Raindrops::new(options.seed);
+                raindrops.settings = scene.raindrops;
+                batch.raindrops = Some(raindrops);
+            }
+            if let Some(size) = options.size {
+                batch.solver.resize(size);
+            }
+        }
+    }
+    if let Some(scene) = &scene {
+        batch.render_config = scene.render;
+        batch.camera_config = scene.camera;
+        batch.lighting = scene.lighting.clone();
+    }
+    if let Some(snapshot) = start {
+        batch.solver.resize(snapshot.size);
+        snapshot.restore(batch.solver.as_mut());
+        batch.tick = snapshot.tick;
+    }
+    batch.grid_extent = batch.render_config.build(batch.solver.size()).1.extent;
+    if let Some(delay) = options.raindrop_delay {
+        let raindrops = batch.raindrops.get_or_insert_with(|| Raindrops::new(options.seed));
+        raindrops.settings.enabled = true;
+        raindrops.settings.delay = delay;
+    }
+
+    let start_tick = batch.tick;
+    let default_ticks = if options.export.is_some() { 0 } else { u64::MAX };
+    batch.run(options.ticks.unwrap_or(default_ticks))?;
+    if let Some(settings) = options.export.clone() {
+        let directory = settings.directory.clone();
+        let mut export = Export::start(settings).map_err(|error| format!("{}: {}", directory.display(), error))?;
+        loop {
+            let frame = batch.draw(export.settings.rotation(0.0, export.written()))?;
+            export.add_frame(&frame).map_err(|error| format!("{}: {}", directory.display(), error))?;
+            if export.is_finished() {
+                break;
+            }
+            batch.run(export.settings.ticks_per_frame as u64)?;
+        }
+        let frames = export.written();
+        export.finish().map_err(|error| format!("{}: {}", directory.display(), error))?;
+        println!("Wrote {} frames to {}", frames, directory.display());
+    }
+    info!("Ran {} ticks on the {} solver", batch.tick - start_tick, batch.solver.kind().name());
+
+    if let Some(path) = &options.output {
+        Snapshot::capture(batch.solver.as_ref(), batch.tick)
+            .save(path)
+            .map_err(|error| format!("{}: {}", path.display(), error))?;
+        println!("Wrote tick {} to {}", batch.tick, path.display());
+    }
+    if let Some(path) = &options.png {
+        batch.draw(0.0)?
+            .save_png(path)
+            .map_err(|error| format!("{}: {}", path.display(), error))?;
+        println!("Drew tick {} to {}", batch.tick, path.display());
+    }
+
+    if let Some(replay) = &batch.replay {
+        if !replay.is_finished(batch.tick) {
+            println!("Stopped before the end of the recording, not checking the result");
+        } else if replay.matches(batch.solver.as_ref()) {
+            println!("Field matches the recording");
+        } else {
+            return Err(format!(
+                "field differs from the recording (checksum {:016x})",
+                record::checksum(&batch.solver.export_points()),
+            ));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,245 @@
+use serde::{Deserialize, Serialize};
+
+/// How much of the wave an absorbing edge eats per tick right at the wall. Tapers off
+/// quadratically towards the inner side of the sponge layer.
+const SPONGE_STRENGTH: f32 = 0.15;
+
+/// What happens to a wave when it reaches the side of the grid. The discriminants are shared
+/// with simulation.wgsl.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum EdgeMode {
+    /// Dirichlet: the water past the edge is held flat, waves reflect upside down
+    Fixed = 0,
+    /// Neumann: the edge mirrors the cell next to it, waves reflect right side up
+    Free = 1,
+    /// Waves leave through this edge and come back in through the opposite one
+    Periodic = 2,
+    /// A sponge layer that soaks up waves before they can reflect
+    Absorbing = 3,
+}
+
+impl EdgeMode {
+    pub const ALL: [EdgeMode; 4] = [EdgeMode::Fixed, EdgeMode::Free, EdgeMode::Periodic, EdgeMode::Absorbing];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EdgeMode::Fixed => "Fixed",
+            EdgeMode::Free => "Free",
+            EdgeMode::Periodic => "Periodic",
+            EdgeMode::Absorbing => "Absorbing",
+        }
+    }
+
+    /// Inverse of `mode as u32`
+    pub fn from_index(index: u32) -> Option<Self> {
+        Self::ALL.get(index as usize).copied()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+impl Edge {
+    pub const ALL: [Edge; 4] = [Edge::Left, Edge::Right, Edge::Top, Edge::Bottom];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Edge::Left => "Left",
+            Edge::Right => "Right",
+            Edge::Top => "Top",
+            Edge::Bottom => "Bottom",
+        }
+    }
+
+    pub fn opposite(&self) -> Edge {
+        match self {
+            Edge::Left => Edge::Right,
+            Edge::Right => Edge::Left,
+            Edge::Top => Edge::Bottom,
+            Edge::Bottom => Edge::Top,
+        }
+    }
+}
+
+/// Per edge boundary conditions. Left/right are the ends of the x axis and top/bottom the ends
+/// of the y axis, matching the texture view.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Boundaries {
+    edges: [EdgeMode; 4],
+    /// Width of the absorbing sponge in cells
+    pub sponge_thickness: u32,
+}
+
+impl Default for Boundaries {
+    fn default() -> Self {
+        Self {
+            edges: [EdgeMode::Fixed; 4],
+            sponge_thickness: 12,
+        }
+    }
+}
+
+impl Boundaries {
+    pub fn get(&self, edge: Edge) -> EdgeMode {
+        self.edges[edge as usize]
+    }
+
+    /// Periodic edges only make sense in pairs, so this keeps the opposite edge in step
+    pub fn set(&mut self, edge: Edge, mode: EdgeMode) {
+        let opposite = edge.opposite();
+        if mode == EdgeMode::Periodic || self.get(opposite) == EdgeMode::Periodic {
+            self.edges[opposite as usize] = mode;
+        }
+        self.edges[edge as usize] = mode;
+    }
+
+    /// Edge modes in Left, Right, Top, Bottom order, as the GPU solver expects them
+    pub fn modes(&self) -> [u32; 4] {
+        self.edges.map(|mode| mode as u32)
+    }
+
+    /// Inverse of `modes`, `None` if any of them isn't a valid `EdgeMode`
+    pub fn from_modes(modes: [u32; 4], sponge_thickness: u32) -> Option<Self> {
+        let mut edges = [EdgeMode::Fixed; 4];
+        for (edge, mode) in edges.iter_mut().zip(modes) {
+            *edge = EdgeMode::from_index(mode)?;
+        }
+        Some(Self { edges, sponge_thickness })
+    }
+
+    /// Cell to read for the neighbour at `(x + dx, y + dy)`, or `None` if it is past a fixed edge
+    /// and should read as flat water
+    pub fn neighbour(
+        &self,
+        x: usize,
+        y: usize,
+        dx: isize,
+        dy: isize,
+        width: usize,
+        height: usize,
+    ) -> Option<(usize, usize)> {
+        let nx = x as isize + dx;
+        let ny = y as isize + dy;
+        let width = width as isize;
+        let height = height as isize;
+        let edge = if nx < 0 {
+            Edge::Left
+        } else if nx >= width {
+            Edge::Right
+        } else if ny < 0 {
+            Edge::Top
+        } else if ny >= height {
+            Edge::Bottom
+        } else {
+            return Some((nx as usize, ny as usize));
+        };
+        match self.get(edge) {
+            EdgeMode::Fixed => None,
+            EdgeMode::Free | EdgeMode::Absorbing => Some((x, y)),
+            EdgeMode::Periodic => Some((nx.rem_euclid(width) as usize, ny.rem_euclid(height) as usize)),
+        }
+    }
+
+    /// Factor to scale a cell by after each tick, 1.0 outside of any sponge layer
+    pub fn absorption(&self, x: usize, y: usize, width: usize, height: usize) -> f32 {
+        let thickness = self.sponge_thickness as usize;
+        if thickness == 0 {
+            return 1.0;
+        }
+        let distances = [x, width - 1 - x, y, height - 1 - y];
+        let mut factor: f32 = 1.0;
+        for (mode, distance) in self.edges.iter().zip(distances) {
+            if *mode == EdgeMode::Absorbing && distance < thickness {
+                let depth = (thickness - distance) as f32 / thickness as f32;
+                factor = factor.min(1.0 - SPONGE_STRENGTH * depth * depth);
+            }
+        }
+        factor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn boundaries(mode: EdgeMode) -> Boundaries {
+        let mut boundaries = Boundaries::default();
+        for edge in Edge::ALL {
+            boundaries.set(edge, mode);
+        }
+        boundaries
+    }
+
+    #[test]
+    fn inner_neighbours_are_left_alone() {
+        let boundaries = boundaries(EdgeMode::Fixed);
+        assert_eq!(boundaries.neighbour(3, 4, 1, 0, 8, 8), Some((4, 4)));
+        assert_eq!(boundaries.neighbour(3, 4, 0, -1, 8, 8), Some((3, 3)));
+    }
+
+    #[test]
+    fn fixed_edges_read_as_flat_water() {
+        let boundaries = boundaries(EdgeMode::Fixed);
+        assert_eq!(boundaries.neighbour(0, 4, -1, 0, 8, 8), None);
+        assert_eq!(boundaries.neighbour(4, 7, 0, 1, 8, 8), None);
+    }
+
+    #[test]
+    fn free_edges_mirror_the_cell() {
+        let boundaries = boundaries(EdgeMode::Free);
+        assert_eq!(boundaries.neighbour(7, 4, 1, 0, 8, 8), Some((7, 4)));
+        assert_eq!(boundaries.neighbour(4, 0, 0, -1, 8, 8), Some((4, 0)));
+    }
+
+    #[test]
+    fn periodic_edges_wrap_around() {
+        let boundaries = boundaries(EdgeMode::Periodic);
+        assert_eq!(boundaries.neighbour(0, 4, -1, 0, 8, 6), Some((7, 4)));
+        assert_eq!(boundaries.neighbour(4, 5, 0, 1, 8, 6), Some((4, 0)));
+    }
+
+    #[test]
+    fn periodic_edges_come_in_pairs() {
+        let mut boundaries = Boundaries::default();
+        boundaries.set(Edge::Left, EdgeMode::Periodic);
+        assert_eq!(boundaries.get(Edge::Right), EdgeMode::Periodic);
+        assert_eq!(boundaries.get(Edge::Top), EdgeMode::Fixed);
+        boundaries.set(Edge::Right, EdgeMode::Free);
+        assert_eq!(boundaries.get(Edge::Left), EdgeMode::Free);
+    }
+
+    #[test]
+    fn sponge_is_strongest_at_the_wall() {
+        let mut boundaries = Boundaries::default();
+        boundaries.set(Edge::Left, EdgeMode::Absorbing);
+        boundaries.sponge_thickness = 4;
+        let factors: Vec<f32> = (0..6).map(|x| boundaries.absorption(x, 10, 20, 20)).collect();
+        assert_eq!(factors[0], 1.0 - SPONGE_STRENGTH);
+        assert!(factors[..5].windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(&factors[4..], [1.0, 1.0]);
+        // Only the absorbing edge has a sponge
+        assert_eq!(boundaries.absorption(19, 10, 20, 20), 1.0);
+    }
+
+    #[test]
+    fn no_sponge_without_thickness() {
+        let mut boundaries = boundaries(EdgeMode::Absorbing);
+        boundaries.sponge_thickness = 0;
+        assert_eq!(boundaries.absorption(0, 0, 8, 8), 1.0);
+    }
+
+    #[test]
+    fn modes_round_trip() {
+        let mut boundaries = Boundaries::default();
+        boundaries.set(Edge::Top, EdgeMode::Periodic);
+        boundaries.set(Edge::Left, EdgeMode::Absorbing);
+        assert_eq!(Boundaries::from_modes(boundaries.modes(), boundaries.sponge_thickness), Some(boundaries));
+        assert_eq!(Boundaries::from_modes([0, 1, 2, 4], 12), None);
+    }
+}
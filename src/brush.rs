@@ -0,0 +1,158 @@
+use macaw::{UVec2, Vec2, vec2};
+use serde::{Deserialize, Serialize};
+use crate::simulation::WavePoint;
+
+/// Medium used for walls, anything negative blocks the wave
+pub const WALL: f32 = -1.0;
+/// Medium of open water
+pub const OPEN: f32 = 1.0;
+
+/// What clicking on the water does
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum BrushTool {
+    /// Disturbs the water
+    Poke,
+    Wall,
+    /// Clears walls and brings painted medium back to open water
+    Erase,
+    /// Paints slow (< 1) or fast (> 1) regions
+    Medium,
+    /// Places a new emitter, see `emitter::Emitter`
+    Emitter,
+}
+
+impl BrushTool {
+    pub const ALL: [BrushTool; 5] = [
+        BrushTool::Poke, BrushTool::Wall, BrushTool::Erase, BrushTool::Medium, BrushTool::Emitter,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BrushTool::Poke => "Poke",
+            BrushTool::Wall => "Wall",
+            BrushTool::Erase => "Erase",
+            BrushTool::Medium => "Medium",
+            BrushTool::Emitter => "Emitter",
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Brush {
+    pub tool: BrushTool,
+    /// In cells
+    pub radius: f32,
+    /// Fraction of the radius that fades out, 0 is a hard edge
+    pub falloff: f32,
+    /// Value painted by `BrushTool::Medium`
+    pub medium: f32,
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self {
+            tool: BrushTool::Poke,
+            radius: 4.0,
+            falloff: 0.5,
+            medium: 0.5,
+        }
+    }
+}
+
+impl Brush {
+    /// Strength of the brush at `distance` cells from its center, in the 0..1 range
+    pub fn weight(&self, distance: f32) -> f32 {
+        let d = distance / self.radius.max(f32::EPSILON);
+        let inner = 1.0 - self.falloff;
+        if d >= 1.0 {
+            0.0
+        } else if d <= inner {
+            1.0
+        } else {
+            let t = (d - inner) / self.falloff;
+            1.0 - t * t * (3.0 - 2.0 * t)
+        }
+    }
+
+    /// Paints the medium of `points` around `center`, given in cells
+    pub fn paint(&self, points: &mut [WavePoint], size: UVec2, center: Vec2) {
+        let width = size.x as usize;
+        let height = size.y as usize;
+        let x_start = (center.x - self.radius).floor().max(0.0) as usize;
+        let y_start = (center.y - self.radius).floor().max(0.0) as usize;
+        let x_end = ((center.x + self.radius).ceil().max(0.0) as usize).min(width);
+        let y_end = ((center.y + self.radius).ceil().max(0.0) as usize).min(height);
+        for y in y_start..y_end {
+            for x in x_start..x_end {
+                let cell_center = vec2(x as f32 + 0.5, y as f32 + 0.5);
+                let weight = self.weight(cell_center.distance(center));
+                if weight <= 0.0 {
+                    continue;
+                }
+                let point = &mut points[y * width + x];
+                match self.tool {
+                    BrushTool::Poke | BrushTool::Emitter => {}
+                    BrushTool::Wall => {
+                        if weight >= 0.5 {
+                            point.medium = WALL;
+                            point.value = 0.0;
+                            point.velocity = 0.0;
+                        }
+                    }
+                    BrushTool::Erase => point.medium = blend(point.medium, OPEN, weight),
+                    BrushTool::Medium => point.medium = blend(point.medium, self.medium, weight),
+                }
+            }
+        }
+    }
+}
+
+/// Moves `medium` towards `target`. Walls are all or nothing, so they only flip once the brush
+/// is strong enough.
+fn blend(medium: f32, target: f32, weight: f32) -> f32 {
+    if medium < 0.0 {
+        if weight >= 0.5 { target } else { medium }
+    } else {
+        medium + (target - medium) * weight
+    }
+}
+
+/// Turns every cell back into open water
+pub fn clear_medium(points: &mut [WavePoint]) {
+    for point in points.iter_mut() {
+        point.medium = OPEN;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brush(falloff: f32) -> Brush {
+        Brush { radius: 4.0, falloff, ..Brush::default() }
+    }
+
+    #[test]
+    fn weight_fades_over_the_falloff() {
+        let brush = brush(0.5);
+        assert_eq!(brush.weight(0.0), 1.0);
+        assert_eq!(brush.weight(2.0), 1.0);
+        assert_eq!(brush.weight(3.0), 0.5);
+        assert_eq!(brush.weight(4.0), 0.0);
+        assert!(brush.weight(2.5) > brush.weight(3.5));
+    }
+
+    #[test]
+    fn no_falloff_is_a_hard_edge() {
+        let brush = brush(0.0);
+        assert_eq!(brush.weight(3.99), 1.0);
+        assert_eq!(brush.weight(4.0), 0.0);
+    }
+
+    #[test]
+    fn walls_only_flip_at_half_strength() {
+        assert_eq!(blend(WALL, OPEN, 0.4), WALL);
+        assert_eq!(blend(WALL, OPEN, 0.5), OPEN);
+        assert_eq!(blend(1.0, 0.5, 0.5), 0.75);
+    }
+}
@@ -1,4 +1,5 @@
-use macaw::{Mat4, Plane3, Vec2, Vec3, Vec4Swizzles};
+use macaw::{Mat4, Plane3, Vec2, Vec3, vec3, Vec4Swizzles};
+use serde::{Deserialize, Serialize};
 
 pub struct Camera {
     pub position: Vec3,
@@ -18,6 +19,46 @@ impl Camera {
     }
 }
 
+/// How the camera circles the prism grid
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraConfig {
+    pub rotation_enabled: bool,
+    pub change_angle: bool,
+    /// Degrees per frame at 60 fps
+    pub rotation_speed: f32,
+    pub distance: f32,
+    pub angle: f32,
+    pub target_ratio: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            rotation_enabled: true,
+            change_angle: false,
+            rotation_speed: 0.10,
+            distance: 1.1,
+            angle: 20.0,
+            target_ratio: 0.0,
+        }
+    }
+}
+
+impl CameraConfig {
+    /// Where the camera is after turning `rotation` radians around a grid of `grid_extent`
+    pub fn orbit(&self, rotation: f32, grid_extent: Vec2) -> Camera {
+        let half_width = grid_extent.max_element() * 0.5;
+        let pos = vec3(rotation.sin(), 0.0, -rotation.cos()) * half_width * self.distance;
+        let mut angle = self.angle.to_radians();
+        if self.change_angle {
+            angle *= 0.6 + 0.4 * rotation.sin();
+        }
+        Camera::new(vec3(pos.x, angle.sin() * half_width, pos.z), pos * self.target_ratio)
+    }
+}
+
+#[derive(Clone)]
 pub struct Projection {
     aspect_ratio: f32,
     fov_y: f32,
@@ -0,0 +1,134 @@
+use std::time::{Duration, Instant};
+
+/// Turns wall clock time into a whole number of simulation ticks, so the waves move at the same
+/// speed whatever the frame rate
+pub struct SimClock {
+    pub ticks_per_second: f32,
+    /// Most ticks to run in one frame. Time past that is dropped so a slow frame doesn't snowball
+    /// into even slower ones.
+    pub max_substeps: u32,
+    /// Stops the ticks, steps queued with `step` still run
+    pub paused: bool,
+    /// Multiplies how fast simulation time passes compared to wall clock time
+    pub time_scale: f32,
+    queued_steps: u32,
+    accumulator: Duration,
+    last_update: Option<Instant>,
+    delta: Duration,
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        Self {
+            ticks_per_second: 60.0,
+            max_substeps: 8,
+            paused: false,
+            time_scale: 1.0,
+            queued_steps: 0,
+            accumulator: Duration::ZERO,
+            last_update: None,
+            delta: Duration::ZERO,
+        }
+    }
+}
+
+impl SimClock {
+    pub fn tick_length(&self) -> Duration {
+        Duration::from_secs_f32(1.0 / self.ticks_per_second.max(1.0))
+    }
+
+    /// Runs `count` extra ticks on the next update, even while paused
+    pub fn step(&mut self, count: u32) {
+        self.queued_steps += count;
+    }
+
+    /// Advances the clock to now and returns how many ticks are due
+    pub fn update(&mut self) -> u32 {
+        let now = Instant::now();
+        self.delta = self.last_update.map_or(Duration::ZERO, |last| now - last);
+        self.last_update = Some(now);
+        let steps = std::mem::take(&mut self.queued_steps);
+        if self.paused {
+            self.accumulator = Duration::ZERO;
+            return steps;
+        }
+        self.accumulator += self.delta.mul_f32(self.time_scale);
+
+        let tick_length = self.tick_length();
+        let mut ticks = 0;
+        while self.accumulator >= tick_length {
+            if ticks == self.max_substeps {
+                self.accumulator = Duration::ZERO;
+                break;
+            }
+            self.accumulator -= tick_length;
+            ticks += 1;
+        }
+        ticks + steps
+    }
+
+    /// Seconds between the last two updates
+    pub fn delta(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    /// How far into the next tick we are, in the 0..1 range
+    pub fn alpha(&self) -> f32 {
+        if self.paused {
+            // Show exactly the state the last step left behind
+            return 1.0;
+        }
+        (self.accumulator.as_secs_f32() / self.tick_length().as_secs_f32()).min(1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A clock that has `ticks` worth of time banked, `update` then adds none of its own
+    fn clock(ticks: u32) -> SimClock {
+        let mut clock = SimClock::default();
+        clock.accumulator = clock.tick_length() * ticks;
+        clock
+    }
+
+    #[test]
+    fn banked_time_runs_as_ticks() {
+        let mut clock = clock(3);
+        assert_eq!(clock.update(), 3);
+        assert_eq!(clock.accumulator, Duration::ZERO);
+    }
+
+    #[test]
+    fn slow_frames_are_capped() {
+        let mut clock = clock(20);
+        clock.max_substeps = 8;
+        assert_eq!(clock.update(), 8);
+        // The rest is dropped rather than run on the next frame
+        assert_eq!(clock.accumulator, Duration::ZERO);
+    }
+
+    #[test]
+    fn queued_steps_run_while_paused() {
+        let mut clock = clock(3);
+        clock.paused = true;
+        clock.step(2);
+        assert_eq!(clock.update(), 2);
+        assert_eq!(clock.update(), 0);
+        assert_eq!(clock.alpha(), 1.0);
+    }
+
+    #[test]
+    fn alpha_is_the_fraction_of_a_tick_left_over() {
+        let mut clock = SimClock::default();
+        clock.accumulator = clock.tick_length() / 4;
+        assert!((clock.alpha() - 0.25).abs() < 1e-3);
+    }
+
+    #[test]
+    fn tick_rate_is_held_above_zero() {
+        let clock = SimClock { ticks_per_second: 0.0, ..SimClock::default() };
+        assert_eq!(clock.tick_length(), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,147 @@
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use macaw::UVec2;
+use serde::{Deserialize, Serialize};
+use crate::brush::Brush;
+use crate::camera::CameraConfig;
+use crate::emitter::Emitter;
+use crate::lighting::Lighting;
+use crate::palette::ColorMap;
+use crate::poke::PokeSettings;
+use crate::raindrops::RaindropSettings;
+use crate::screenshot::ScreenshotSettings;
+use crate::sim_renderer::{Antialiasing, RenderConfig, RenderMode};
+use crate::simulation;
+use crate::solver::{SimParams, SolverKind};
+
+/// State of the settings window, saved on exit and picked back up on the next launch. What's
+/// in the water (emitters, walls, the field itself) belongs in scenes and snapshots instead.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub solver: SolverKind,
+    pub sim_size: UVec2,
+    pub params: SimParams,
+    pub ticks_per_second: f32,
+    pub max_substeps: u32,
+    pub time_scale: f32,
+    pub interpolate: bool,
+    pub step_count: u32,
+    pub brush: Brush,
+    pub poke: PokeSettings,
+    pub new_emitter: Emitter,
+    pub raindrops: RaindropSettings,
+    pub render_mode: RenderMode,
+    pub render: RenderConfig,
+    /// Falls back to FXAA on adapters that can't draw with that many samples
+    pub antialiasing: Antialiasing,
+    /// Indexed by `RenderMode`
+    pub color_maps: [ColorMap; 2],
+    /// Custom palettes loaded on startup
+    pub palette_file: Option<PathBuf>,
+    pub camera: CameraConfig,
+    pub lighting: Lighting,
+    pub screenshot: ScreenshotSettings,
+    /// Seconds to blend into a newly picked preset over, 0 to switch straight away
+    pub preset_transition: f32,
+    pub show_settings: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            solver: SolverKind::Cpu,
+            sim_size: simulation::DEFAULT_SIZE,
+            params: SimParams::default(),
+            ticks_per_second: 60.0,
+            max_substeps: 8,
+            time_scale: 1.0,
+            interpolate: true,
+            step_count: 10,
+            brush: Brush::default(),
+            poke: PokeSettings::default(),
+            new_emitter: Emitter::default(),
+            raindrops: RaindropSettings::default(),
+            render_mode: RenderMode::Prism,
+            render: RenderConfig::default(),
+            antialiasing: Antialiasing::Off,
+            color_maps: RenderMode::ALL.map(ColorMap::default_for),
+            palette_file: None,
+            camera: CameraConfig::default(),
+            lighting: Lighting::default(),
+            screenshot: ScreenshotSettings::default(),
+            preset_transition: 1.0,
+            show_settings: true,
+        }
+    }
+}
+
+/// Holds a value to the range its slider in the settings window goes over
+fn check_range<T: PartialOrd + Display>(name: &str, value: T, range: RangeInclusive<T>) -> Result<(), String> {
+    if !range.contains(&value) {
+        return Err(format!("{} {} isn't between {} and {}", name, value, range.start(), range.end()));
+    }
+    Ok(())
+}
+
+/// `ripple` in the platform's config directory, e.g. `~/.config` on Linux
+pub fn directory() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join("ripple"))
+}
+
+impl Config {
+    /// `settings.ron` in `directory`
+    pub fn path() -> Option<PathBuf> {
+        Some(directory()?.join("settings.ron"))
+    }
+
+    /// The saved settings, or `None` if nothing has been saved yet
+    pub fn load() -> io::Result<Option<Self>> {
+        let Some(path) = Self::path() else {
+            return Ok(None);
+        };
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        let config: Config = ron::from_str(&text)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        config.check().map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        Ok(Some(config))
+    }
+
+    /// Catches hand edited values the app would panic on
+    fn check(&self) -> Result<(), String> {
+        simulation::check_size(self.sim_size)?;
+        if !(self.time_scale.is_finite() && self.time_scale >= 0.0) {
+            return Err(format!("time scale {} has to be 0 or more", self.time_scale));
+        }
+        if !(self.ticks_per_second.is_finite() && self.ticks_per_second > 0.0) {
+            return Err(format!("ticks per second {} has to be more than 0", self.ticks_per_second));
+        }
+        check_range("prism grid size", self.render.grid_size, RenderConfig::GRID_SIZES)?;
+        check_range("step size", self.render.step_size, RenderConfig::STEP_SIZES)?;
+        check_range("prism height", self.render.prism_height, RenderConfig::PRISM_HEIGHTS)?;
+        for (name, poke) in [("poke", &self.poke), ("raindrop poke", &self.raindrops.poke)] {
+            check_range(&format!("{} radius", name), poke.radius, PokeSettings::RADII)?;
+            check_range(&format!("{} amplitude", name), poke.amplitude, PokeSettings::AMPLITUDES)?;
+        }
+        Ok(())
+    }
+
+    pub fn save(&self) -> io::Result<PathBuf> {
+        let path = Self::path()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        fs::write(&path, text)?;
+        Ok(path)
+    }
+}
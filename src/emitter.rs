@@ -0,0 +1,145 @@
+use std::f32::consts::TAU;
+use macaw::{UVec2, Vec2};
+use serde::{Deserialize, Serialize};
+use crate::solver::Source;
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum EmitterShape {
+    Point,
+    /// A straight run of cells between `start` and `end`, e.g. a plane wave or a slit
+    Line,
+}
+
+impl EmitterShape {
+    pub const ALL: [EmitterShape; 2] = [EmitterShape::Point, EmitterShape::Line];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EmitterShape::Point => "Point",
+            EmitterShape::Line => "Line",
+        }
+    }
+}
+
+/// A wave source that keeps driving the water up and down with a sine wave
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Emitter {
+    pub enabled: bool,
+    pub shape: EmitterShape,
+    /// In the 0..1 range so emitters stay put when the grid is resized
+    pub start: Vec2,
+    /// Other end of a line emitter, unused for points
+    pub end: Vec2,
+    /// In cells
+    pub radius: f32,
+    /// Cycles per tick
+    pub frequency: f32,
+    pub amplitude: f32,
+    /// In radians
+    pub phase: f32,
+    /// Fraction of each cycle the emitter drives the water, 1 for a continuous wave
+    pub duty_cycle: f32,
+}
+
+impl Default for Emitter {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            shape: EmitterShape::Point,
+            start: Vec2::splat(0.5),
+            end: Vec2::splat(0.5),
+            radius: 1.5,
+            frequency: 0.05,
+            amplitude: 1.0,
+            phase: 0.0,
+            duty_cycle: 1.0,
+        }
+    }
+}
+
+impl Emitter {
+    /// Height the emitter holds its cells at on the given tick, `None` while the duty cycle has
+    /// it switched off
+    pub fn value(&self, tick: u64) -> Option<f32> {
+        let cycle = (self.frequency as f64 * tick as f64 + (self.phase / TAU) as f64).rem_euclid(1.0) as f32;
+        if !self.enabled || cycle >= self.duty_cycle {
+            return None;
+        }
+        Some(self.amplitude * (cycle * TAU).sin())
+    }
+
+    pub fn source(&self, tick: u64, sim_size: UVec2) -> Option<Source> {
+        let scale = sim_size.as_vec2();
+        let start = self.start * scale;
+        let end = match self.shape {
+            EmitterShape::Point => start,
+            EmitterShape::Line => self.end * scale,
+        };
+        Some(Source {
+            start,
+            end,
+            radius: self.radius,
+            value: self.value(tick)?,
+        })
+    }
+}
+
+/// Sources for every emitter that is driving the water on `tick`
+pub fn sources(emitters: &[Emitter], tick: u64, sim_size: UVec2) -> Vec<Source> {
+    emitters.iter()
+        .filter_map(|emitter| emitter.source(tick, sim_size))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macaw::{uvec2, vec2};
+
+    #[test]
+    fn value_follows_a_sine_wave() {
+        let emitter = Emitter { frequency: 0.25, amplitude: 2.0, ..Emitter::default() };
+        let values: Vec<f32> = (0..5).map(|tick| emitter.value(tick).unwrap()).collect();
+        for (value, expected) in values.iter().zip([0.0, 2.0, 0.0, -2.0, 0.0]) {
+            assert!((value - expected).abs() < 1e-5, "{:?}", values);
+        }
+    }
+
+    #[test]
+    fn phase_shifts_the_wave() {
+        let emitter = Emitter { frequency: 0.25, phase: TAU / 4.0, ..Emitter::default() };
+        assert!((emitter.value(0).unwrap() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn duty_cycle_switches_off_the_rest_of_the_cycle() {
+        let emitter = Emitter { frequency: 0.25, duty_cycle: 0.5, ..Emitter::default() };
+        assert!(emitter.value(1).is_some());
+        assert_eq!(emitter.value(2), None);
+        assert_eq!(emitter.value(3), None);
+        assert!(emitter.value(4).is_some());
+    }
+
+    #[test]
+    fn disabled_emitters_have_no_source() {
+        let emitter = Emitter { enabled: false, ..Emitter::default() };
+        assert_eq!(emitter.value(0), None);
+        assert!(sources(&[emitter], 0, uvec2(8, 8)).is_empty());
+    }
+
+    #[test]
+    fn sources_are_scaled_to_cells() {
+        let emitter = Emitter {
+            shape: EmitterShape::Line,
+            start: vec2(0.25, 0.5),
+            end: vec2(0.75, 0.5),
+            ..Emitter::default()
+        };
+        let source = emitter.source(0, uvec2(64, 32)).unwrap();
+        assert_eq!(source.start, vec2(16.0, 16.0));
+        assert_eq!(source.end, vec2(48.0, 16.0));
+        let point = Emitter { shape: EmitterShape::Point, ..emitter }.source(0, uvec2(64, 32)).unwrap();
+        assert_eq!(point.end, point.start);
+    }
+}
@@ -0,0 +1,218 @@
+use std::f32::consts::TAU;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use macaw::{UVec2, uvec2};
+use crate::render_target::Frame;
+
+/// Container for the clip written next to the PNG sequence
+#[derive(Copy, Clone, PartialEq)]
+pub enum VideoFormat {
+    /// Uncompressed YUV 4:4:4, for feeding into an encoder
+    Y4m,
+    /// Looping animated GIF with a palette per frame
+    Gif,
+}
+
+impl VideoFormat {
+    pub const ALL: [VideoFormat; 2] = [VideoFormat::Y4m, VideoFormat::Gif];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            VideoFormat::Y4m => "Y4M",
+            VideoFormat::Gif => "GIF",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            VideoFormat::Y4m => "y4m",
+            VideoFormat::Gif => "gif",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?;
+        Self::ALL.into_iter().find(|format| format.extension().eq_ignore_ascii_case(extension))
+    }
+}
+
+/// What to render. Every frame is `ticks_per_frame` ticks after the one before, however long it
+/// takes to draw, so the clip plays back at a steady speed.
+#[derive(Clone)]
+pub struct ExportSettings {
+    pub frames: u32,
+    pub ticks_per_frame: u32,
+    pub fps: u32,
+    pub size: UVec2,
+    /// Turns the camera once all the way round over the clip, so it loops without a jump
+    pub orbit: bool,
+    /// Where the numbered PNGs go, created if missing
+    pub directory: PathBuf,
+    /// Also writes the frames into a single file, in the format matching its extension
+    pub video: Option<PathBuf>,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            frames: 300,
+            ticks_per_frame: 2,
+            fps: 30,
+            size: uvec2(1280, 720),
+            orbit: true,
+            directory: "export".into(),
+            video: None,
+        }
+    }
+}
+
+impl ExportSettings {
+    /// Camera rotation for frame `index` of the clip. The last frame stops one step short of
+    /// `start` so the first frame follows on from it.
+    pub fn rotation(&self, start: f32, index: u32) -> f32 {
+        if self.orbit {
+            start + TAU * index as f32 / self.frames as f32
+        } else {
+            start
+        }
+    }
+}
+
+/// An export in progress, fed one frame at a time
+pub struct Export {
+    pub settings: ExportSettings,
+    written: u32,
+    video: Option<VideoWriter>,
+}
+
+impl Export {
+    pub fn start(settings: ExportSettings) -> io::Result<Self> {
+        fs::create_dir_all(&settings.directory)?;
+        let video = match &settings.video {
+            Some(path) => Some(VideoWriter::create(path, &settings)?),
+            None => None,
+        };
+        Ok(Self {
+            settings,
+            written: 0,
+            video,
+        })
+    }
+
+    /// Frames written so far, which is also the index of the next one
+    pub fn written(&self) -> u32 {
+        self.written
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.written >= self.settings.frames
+    }
+
+    pub fn add_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        if frame.size != self.settings.size {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "frame doesn't match the export size"));
+        }
+        frame.save_png(&self.settings.directory.join(format!("frame_{:05}.png", self.written)))?;
+        if let Some(video) = &mut self.video {
+            video.write_frame(frame)?;
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Flushes the video file. Dropping an unfinished export leaves what was written so far.
+    pub fn finish(self) -> io::Result<()> {
+        match self.video {
+            Some(VideoWriter::Y4m(mut file)) => file.flush(),
+            Some(VideoWriter::Gif { encoder, .. }) => encoder.into_inner()?.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+enum VideoWriter {
+    Y4m(BufWriter<File>),
+    Gif {
+        encoder: gif::Encoder<BufWriter<File>>,
+        fps: u32,
+        /// Frames written so far, so rounded delays add up to the right total
+        frames: u64,
+    },
+}
+
+impl VideoWriter {
+    fn create(path: &Path, settings: &ExportSettings) -> io::Result<Self> {
+        let format = VideoFormat::from_path(path).ok_or_else(|| io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} isn't a .y4m or .gif file", path.display()),
+        ))?;
+        let file = BufWriter::new(File::create(path)?);
+        match format {
+            VideoFormat::Y4m => {
+                let mut file = file;
+                writeln!(
+                    file, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444",
+                    settings.size.x, settings.size.y, settings.fps,
+                )?;
+                Ok(VideoWriter::Y4m(file))
+            }
+            VideoFormat::Gif => {
+                let (width, height) = gif_size(settings.size)?;
+                let mut encoder = gif::Encoder::new(file, width, height, &[]).map_err(io::Error::other)?;
+                encoder.set_repeat(gif::Repeat::Infinite).map_err(io::Error::other)?;
+                Ok(VideoWriter::Gif { encoder, fps: settings.fps.max(1), frames: 0 })
+            }
+        }
+    }
+
+    fn write_frame(&mut self, frame: &Frame) -> io::Result<()> {
+        match self {
+            VideoWriter::Y4m(file) => {
+                file.write_all(b"FRAME\n")?;
+                for plane in yuv_planes(&frame.pixels) {
+                    file.write_all(&plane)?;
+                }
+                Ok(())
+            }
+            VideoWriter::Gif { encoder, fps, frames } => {
+                let (width, height) = gif_size(frame.size)?;
+                let mut pixels = frame.pixels.clone();
+                let mut gif_frame = gif::Frame::from_rgba_speed(width, height, &mut pixels, 10);
+                gif_frame.delay = gif_delay(*frames, *fps);
+                *frames += 1;
+                encoder.write_frame(&gif_frame).map_err(io::Error::other)
+            }
+        }
+    }
+}
+
+/// Hundredths of a second to show `frame` for. GIF delays are whole hundredths, so each frame
+/// ends on the rounded time of its end to stop the error adding up, and is shown for at least
+/// one hundredth.
+fn gif_delay(frame: u64, fps: u32) -> u16 {
+    let end = |frame: u64| (frame * 100 + fps as u64 / 2) / fps as u64;
+    (end(frame + 1) - end(frame)).clamp(1, u16::MAX as u64) as u16
+}
+
+fn gif_size(size: UVec2) -> io::Result<(u16, u16)> {
+    match (u16::try_from(size.x), u16::try_from(size.y)) {
+        (Ok(width), Ok(height)) => Ok((width, height)),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "too big for a GIF")),
+    }
+}
+
+/// Splits RGBA pixels into full size BT.601 studio range Y, Cb and Cr planes
+fn yuv_planes(pixels: &[u8]) -> [Vec<u8>; 3] {
+    let mut planes = [(); 3].map(|_| Vec::with_capacity(pixels.len() / 4));
+    for pixel in pixels.chunks_exact(4) {
+        let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(|channel| channel as f32 / 255.0);
+        let y = 16.0 + 65.481 * r + 128.553 * g + 24.966 * b;
+        let cb = 128.0 - 37.797 * r - 74.203 * g + 112.0 * b;
+        let cr = 128.0 + 112.0 * r - 93.786 * g - 18.214 * b;
+        for (plane, value) in planes.iter_mut().zip([y, cb, cr]) {
+            plane.push(value.round() as u8);
+        }
+    }
+    planes
+}
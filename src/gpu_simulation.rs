@@ -0,0 +1,465 @@
+use std::cell::{Cell, RefCell};
+use std::sync::Arc;
+use bytemuck::{Pod, Zeroable};
+use macaw::{UVec2, Vec2};
+use crate::poke::Poke;
+use crate::simulation::{self, WavePoint};
+use crate::solver::{self, SimParams, Source, SolverKind, SolverOutput, WaveSolver};
+
+const WORKGROUP_SIZE: u32 = 8;
+/// Has to match MAX_POKES in simulation.wgsl
+const MAX_POKES: usize = 32;
+const TEXEL_SIZE: u32 = size_of::<WavePoint>() as u32;
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GpuPoke {
+    /// (center.x, center.y, radius, amplitude), all in cells
+    area: [f32; 4],
+    /// (shape, unused, unused, unused)
+    shape: [u32; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Pod, Zeroable)]
+struct GpuSimParams {
+    damping: f32,
+    poke_count: u32,
+    sponge_thickness: u32,
+    source_count: u32,
+    edges: [u32; 4],
+    pokes: [GpuPoke; MAX_POKES],
+    /// Segment of each source in cells: (start.x, start.y, end.x, end.y)
+    source_segments: [[f32; 4]; solver::MAX_SOURCES],
+    /// (value, radius, unused, unused)
+    source_values: [[f32; 4]; solver::MAX_SOURCES],
+}
+
+#[derive(Copy, Clone)]
+enum Pass {
+    Advance,
+    Poke,
+}
+
+/// Runs the same update rule as `WaveSimulation` in a compute shader. The field lives in two
+/// storage textures that are ping-ponged every pass, so the state never has to leave the GPU.
+pub struct GpuWaveSimulation {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    params: SimParams,
+    field: GpuField,
+    bind_group_layout: wgpu::BindGroupLayout,
+    params_buffer: wgpu::Buffer,
+    advance_pipeline: wgpu::ComputePipeline,
+    poke_pipeline: wgpu::ComputePipeline,
+    /// Applied by the next `advance`, or by `flush_pokes` if the field is read or replaced first
+    pending_pokes: RefCell<Vec<GpuPoke>>,
+    sources: Vec<Source>,
+}
+
+/// The size dependent half of the simulation, rebuilt on resize
+struct GpuField {
+    size: UVec2,
+    textures: [wgpu::Texture; 2],
+    /// bind_groups[i] reads from textures[i] and writes to the other one
+    bind_groups: [wgpu::BindGroup; 2],
+    /// Index into `textures` holding the latest state
+    current: Cell<usize>,
+}
+
+impl GpuWaveSimulation {
+    pub fn new(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>, size: UVec2) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/simulation.wgsl"));
+
+        let params_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("gpu_sim_params"),
+            size: size_of::<GpuSimParams>() as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: wgpu::TextureFormat::Rgba32Float,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("gpu_sim_bind_group_layout"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("gpu sim pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let create_pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &shader,
+                entry_point: Some(entry_point),
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            })
+        };
+        let advance_pipeline = create_pipeline("advance");
+        let poke_pipeline = create_pipeline("apply_pokes");
+
+        let field = GpuField::new(&device, &bind_group_layout, &params_buffer, size);
+        let mut simulation = Self {
+            device,
+            queue,
+            params: SimParams::default(),
+            field,
+            bind_group_layout,
+            params_buffer,
+            advance_pipeline,
+            poke_pipeline,
+            pending_pokes: RefCell::new(Vec::new()),
+            sources: Vec::new(),
+        };
+        // Textures start zeroed, but still water has a medium of 1
+        simulation.upload(&vec![WavePoint::default(); (size.x * size.y) as usize]);
+        simulation
+    }
+
+    /// Replaces the whole field, e.g. when switching over from the CPU backend
+    fn upload(&mut self, points: &[WavePoint]) {
+        // Both textures, so the renderer doesn't blend with a stale previous state
+        self.upload_state(points, points);
+    }
+
+    fn upload_state(&mut self, current: &[WavePoint], previous: &[WavePoint]) {
+        // Pokes land before the upload, like they would on the CPU solver
+        self.flush_pokes();
+        self.write_texture(self.current_texture(), current);
+        self.write_texture(self.previous_texture(), previous);
+    }
+
+    /// Runs the pending pokes now instead of at the next `advance`. The poke pass writes over the
+    /// previous state, so it's parked in a spare texture and put back afterwards.
+    fn flush_pokes(&self) {
+        let pokes = self.pending_pokes.take();
+        if pokes.is_empty() {
+            return;
+        }
+        let previous = self.previous_texture();
+        let parked = self.device.create_texture(&wgpu::TextureDescriptor {
+            size: previous.size(),
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: previous.format(),
+            usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+            label: Some("gpu_sim_parked_previous"),
+            view_formats: &[],
+        });
+        let mut encoder = self.device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("gpu sim park encoder") }
+        );
+        encoder.copy_texture_to_texture(previous.as_image_copy(), parked.as_image_copy(), parked.size());
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        for chunk in pokes.chunks(MAX_POKES) {
+            self.dispatch(Pass::Poke, chunk);
+        }
+
+        let mut encoder = self.device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("gpu sim restore encoder") }
+        );
+        encoder.copy_texture_to_texture(
+            parked.as_image_copy(),
+            self.previous_texture().as_image_copy(),
+            parked.size(),
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    fn write_texture(&self, texture: &wgpu::Texture, points: &[WavePoint]) {
+        let size = self.field.size;
+        self.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            bytemuck::cast_slice(points),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(TEXEL_SIZE * size.x),
+                rows_per_image: Some(size.y),
+            },
+            texture.size(),
+        );
+    }
+
+    fn dispatch(
+        &self,
+        pass: Pass,
+        pokes: &[GpuPoke],
+    ) {
+        let pipeline = match pass {
+            Pass::Advance => &self.advance_pipeline,
+            Pass::Poke => &self.poke_pipeline,
+        };
+        let mut params = GpuSimParams {
+            damping: self.params.damping,
+            poke_count: pokes.len() as u32,
+            sponge_thickness: self.params.boundaries.sponge_thickness,
+            source_count: self.sources.len() as u32,
+            edges: self.params.boundaries.modes(),
+            pokes: [GpuPoke::zeroed(); MAX_POKES],
+            source_segments: [[0.0; 4]; solver::MAX_SOURCES],
+            source_values: [[0.0; 4]; solver::MAX_SOURCES],
+        };
+        params.pokes[..pokes.len()].copy_from_slice(pokes);
+        for (i, source) in self.sources.iter().enumerate() {
+            params.source_segments[i] = [source.start.x, source.start.y, source.end.x, source.end.y];
+            params.source_values[i] = [source.value, source.radius, 0.0, 0.0];
+        }
+        self.queue.write_buffer(&self.params_buffer, 0, bytemuck::bytes_of(&params));
+
+        let mut encoder = self.device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("gpu sim encoder") }
+        );
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("gpu sim pass"),
+                timestamp_writes: None,
+            });
+            let size = self.field.size;
+            compute_pass.set_pipeline(pipeline);
+            compute_pass.set_bind_group(0, &self.field.bind_groups[self.field.current.get()], &[]);
+            compute_pass.dispatch_workgroups(
+                size.x.div_ceil(WORKGROUP_SIZE),
+                size.y.div_ceil(WORKGROUP_SIZE),
+                1,
+            );
+        }
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.field.current.set(1 - self.field.current.get());
+    }
+
+    /// Texture holding the latest state, laid out the same as `SimTextureData`
+    fn current_texture(&self) -> &wgpu::Texture {
+        &self.field.textures[self.field.current.get()]
+    }
+
+    /// The other half of the ping-pong pair, holding the input to the last pass
+    fn previous_texture(&self) -> &wgpu::Texture {
+        &self.field.textures[1 - self.field.current.get()]
+    }
+
+    fn read_state(&self) -> Vec<WavePoint> {
+        self.flush_pokes();
+        self.read_texture(self.current_texture())
+    }
+
+    /// Copies a texture of the field back to the CPU. This stalls until the GPU is done, so it
+    /// is meant for syncing backends and debugging rather than every frame.
+    fn read_texture(&self, texture: &wgpu::Texture) -> Vec<WavePoint> {
+        let size = self.field.size;
+        let unpadded_bytes_per_row = TEXEL_SIZE * size.x;
+        let padded_bytes_per_row = unpadded_bytes_per_row.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("gpu_sim_readback"),
+            size: (padded_bytes_per_row * size.y) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self.device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("gpu sim readback encoder") }
+        );
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_bytes_per_row),
+                    rows_per_image: Some(size.y),
+                },
+            },
+            texture.size(),
+        );
+        self.queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        self.device.poll(wgpu::Maintain::Wait);
+
+        let mut points = Vec::with_capacity((size.x * size.y) as usize);
+        {
+            let data = slice.get_mapped_range();
+            for row in data.chunks(padded_bytes_per_row as usize) {
+                points.extend_from_slice(bytemuck::cast_slice(&row[..unpadded_bytes_per_row as usize]));
+            }
+        }
+        buffer.unmap();
+        points
+    }
+}
+
+impl WaveSolver for GpuWaveSimulation {
+    fn kind(&self) -> SolverKind {
+        SolverKind::Gpu
+    }
+
+    fn size(&self) -> UVec2 {
+        self.field.size
+    }
+
+    fn params(&self) -> &SimParams {
+        &self.params
+    }
+
+    fn params_mut(&mut self) -> &mut SimParams {
+        &mut self.params
+    }
+
+    fn advance(&mut self) {
+        let pokes = self.pending_pokes.take();
+        for chunk in pokes.chunks(MAX_POKES) {
+            self.dispatch(Pass::Poke, chunk);
+        }
+        self.dispatch(Pass::Advance, &[]);
+    }
+
+    fn set_sources(&mut self, sources: &[Source]) {
+        self.sources = sources.iter().take(solver::MAX_SOURCES).copied().collect();
+    }
+
+    /// Pokes are batched and applied at the start of the next `advance`, see `flush_pokes`
+    fn poke(&mut self, poke: &Poke, center: Vec2) {
+        self.pending_pokes.get_mut().push(GpuPoke {
+            area: [center.x, center.y, poke.radius, poke.amplitude],
+            shape: [poke.shape as u32, 0, 0, 0],
+        });
+    }
+
+    fn reset(&mut self) {
+        let mut points = self.read_state();
+        for point in points.iter_mut() {
+            point.value = 0.0;
+            point.velocity = 0.0;
+        }
+        self.upload(&points);
+    }
+
+    fn resize(&mut self, size: UVec2) {
+        let points = simulation::resample(&self.read_state(), self.field.size, size);
+        self.field = GpuField::new(&self.device, &self.bind_group_layout, &self.params_buffer, size);
+        self.upload(&points);
+    }
+
+    fn export_points(&self) -> Vec<WavePoint> {
+        self.read_state()
+    }
+
+    fn import_points(&mut self, points: &[WavePoint]) {
+        self.upload(points);
+    }
+
+    fn export_previous_points(&self) -> Vec<WavePoint> {
+        self.flush_pokes();
+        self.read_texture(self.previous_texture())
+    }
+
+    fn import_state(&mut self, current: &[WavePoint], previous: &[WavePoint]) {
+        self.upload_state(current, previous);
+    }
+
+    fn output(&self) -> SolverOutput<'_> {
+        SolverOutput::Texture {
+            current: self.current_texture(),
+            previous: self.previous_texture(),
+        }
+    }
+}
+
+impl GpuField {
+    fn new(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        params_buffer: &wgpu::Buffer,
+        size: UVec2,
+    ) -> Self {
+        let texture_size = wgpu::Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        };
+        let textures = ["gpu_sim_texture_a", "gpu_sim_texture_b"].map(|label| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                size: texture_size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba32Float,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::STORAGE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC
+                    | wgpu::TextureUsages::COPY_DST,
+                label: Some(label),
+                view_formats: &[],
+            })
+        });
+        let views = textures.each_ref()
+            .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()));
+        let bind_groups = [(0, 1), (1, 0)].map(|(src, dst)| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                layout: bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&views[src]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(&views[dst]),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: params_buffer.as_entire_binding(),
+                    },
+                ],
+                label: Some("gpu_sim_bind_group"),
+            })
+        });
+        Self {
+            size,
+            textures,
+            bind_groups,
+            current: Cell::new(0),
+        }
+    }
+}
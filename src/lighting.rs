@@ -0,0 +1,175 @@
+use macaw::{Mat4, Vec2, Vec3, vec3};
+use serde::{Deserialize, Serialize};
+
+/// Most lights the prism shader handles, has to match the `lights` array in pipeline_prism.wgsl
+pub const MAX_LIGHTS: usize = 4;
+
+/// How far waves can push the prisms up or down. The prism shader raises them 15 units per unit
+/// of height, this leaves room for waves a couple of units high.
+const WAVE_MARGIN: f32 = 30.0;
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum LightSource {
+    /// Shines evenly across the whole grid, like the sun
+    Directional { direction: Vec3 },
+    /// Fades with distance. The position is in half grid widths from the middle of the grid,
+    /// like the camera distance, so it stays put when the grid changes size.
+    Point { position: Vec3 },
+}
+
+impl LightSource {
+    pub fn name(&self) -> &'static str {
+        match self {
+            LightSource::Directional { .. } => "Directional",
+            LightSource::Point { .. } => "Point",
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Light {
+    pub enabled: bool,
+    pub source: LightSource,
+    /// Linear RGB
+    pub color: [f32; 3],
+    pub intensity: f32,
+}
+
+impl Default for Light {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            source: LightSource::Point { position: vec3(0.0, 1.0, 0.0) },
+            color: [1.0; 3],
+            intensity: 0.5,
+        }
+    }
+}
+
+/// How the prisms are lit, with Blinn-Phong highlights
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Lighting {
+    /// Linear RGB light that reaches every face
+    pub ambient: [f32; 3],
+    /// Strength of the highlights
+    pub specular: f32,
+    /// Higher is a smaller, sharper highlight
+    pub shininess: f32,
+    /// Only the first `MAX_LIGHTS` are used
+    pub lights: Vec<Light>,
+    /// Whether the first directional light casts shadows
+    pub shadows: bool,
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Self {
+            ambient: [0.12; 3],
+            specular: 0.4,
+            shininess: 32.0,
+            lights: vec![
+                // Low and from the side, so the tall prisms get a lit and a shaded face
+                Light {
+                    source: LightSource::Directional { direction: vec3(-0.5, -0.8, 0.35) },
+                    color: [1.0, 0.95, 0.85],
+                    intensity: 0.9,
+                    ..Light::default()
+                },
+                Light {
+                    source: LightSource::Point { position: vec3(0.3, 1.2, -0.2) },
+                    color: [0.6, 0.7, 1.0],
+                    intensity: 0.6,
+                    ..Light::default()
+                },
+            ],
+            shadows: true,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct LightUniform {
+    /// Direction for directional lights with w = 0, world position for point lights with w = 1
+    vector: [f32; 4],
+    /// Colour times intensity
+    color: [f32; 4],
+}
+
+/// Matches `Lighting` in pipeline_prism.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightingUniform {
+    /// World space to the shadow map, see `shadow_view_proj`
+    shadow_view_proj: [f32; 16],
+    ambient: [f32; 3],
+    light_count: u32,
+    specular: f32,
+    shininess: f32,
+    /// Distance at which point lights are down to half strength
+    falloff: f32,
+    /// Index of the light casting shadows, `MAX_LIGHTS` for none
+    shadow_light: u32,
+    lights: [LightUniform; MAX_LIGHTS],
+}
+
+impl LightingUniform {
+    /// Whether the shadow map has to be drawn
+    pub fn casts_shadows(&self) -> bool {
+        self.shadow_light < self.light_count
+    }
+}
+
+impl Lighting {
+    /// The uniform for a prism grid of `grid_extent`, see `MeshGrid::extent`, with prisms
+    /// `prism_height` tall
+    pub fn uniform(&self, grid_extent: Vec2, prism_height: f32) -> LightingUniform {
+        let half_width = grid_extent.max_element() * 0.5;
+        let mut uniform = LightingUniform {
+            shadow_view_proj: Mat4::IDENTITY.to_cols_array(),
+            ambient: self.ambient,
+            light_count: 0,
+            specular: self.specular,
+            shininess: self.shininess,
+            falloff: half_width,
+            shadow_light: MAX_LIGHTS as u32,
+            lights: [LightUniform { vector: [0.0; 4], color: [0.0; 4] }; MAX_LIGHTS],
+        };
+        for light in self.lights.iter().filter(|light| light.enabled).take(MAX_LIGHTS) {
+            let vector = match light.source {
+                LightSource::Directional { direction } => {
+                    let direction = direction.normalize_or_zero();
+                    if self.shadows && uniform.shadow_light == MAX_LIGHTS as u32 && direction != Vec3::ZERO {
+                        uniform.shadow_light = uniform.light_count;
+                        uniform.shadow_view_proj = shadow_view_proj(direction, grid_extent, prism_height)
+                            .to_cols_array();
+                    }
+                    direction.extend(0.0)
+                }
+                LightSource::Point { position } => (position * half_width).extend(1.0),
+            };
+            let [r, g, b] = light.color.map(|channel| channel * light.intensity);
+            uniform.lights[uniform.light_count as usize] = LightUniform {
+                vector: vector.to_array(),
+                color: [r, g, b, 0.0],
+            };
+            uniform.light_count += 1;
+        }
+        uniform
+    }
+}
+
+/// Orthographic view along `direction` just big enough to take in the whole grid, so the shadow
+/// map isn't spread over empty space
+fn shadow_view_proj(direction: Vec3, grid_extent: Vec2, prism_height: f32) -> Mat4 {
+    // The prisms hang down from their tops at y = 0
+    let half_height = prism_height * 0.5 + WAVE_MARGIN;
+    let center = vec3(0.0, prism_height * -0.5, 0.0);
+    let radius = vec3(grid_extent.x * 0.5, half_height, grid_extent.y * 0.5).length();
+    let up = if direction.y.abs() > 0.99 { Vec3::Z } else { Vec3::Y };
+    let view = Mat4::look_at_lh(center - direction * radius, center, up);
+    let projection = Mat4::orthographic_lh(-radius, radius, -radius, radius, 0.0, 2.0 * radius);
+    projection * view
+}
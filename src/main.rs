@@ -6,95 +6,365 @@ mod mesh_grid;
 mod texture;
 mod egui_renderer;
 mod sim_renderer;
+mod gpu_simulation;
+mod solver;
+mod boundary;
+mod brush;
+mod clock;
+mod emitter;
+mod poke;
+mod stroke;
+mod snapshot;
+mod raindrops;
+mod record;
+mod batch;
+mod render_target;
+mod export;
+mod screenshot;
+mod scene;
+mod config;
+mod preset;
+mod shader_watch;
+mod palette;
+mod lighting;
 
+use std::convert::Infallible;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::SystemTime;
 use egui::Widget;
 use log::info;
-use macaw::{Plane3, Vec2, vec2, vec3, Vec3Swizzles};
+use macaw::{Plane3, UVec2, Vec2, vec2, vec3, Vec3Swizzles};
 use winit::application::ApplicationHandler;
-use winit::event::ElementState::Pressed;
+use winit::event::ElementState::{Pressed, Released};
 use winit::event::{KeyEvent, WindowEvent};
 use winit::event_loop::{ActiveEventLoop, ControlFlow, EventLoop};
 use winit::keyboard::{KeyCode, PhysicalKey};
-use winit::window::{Window, WindowId};
-use crate::camera::Camera;
+use winit::dpi::PhysicalSize;
+use winit::window::{Fullscreen, Window, WindowId};
+use crate::boundary::{Edge, EdgeMode};
+use crate::brush::{Brush, BrushTool};
+use crate::camera::{Camera, CameraConfig};
+use crate::clock::SimClock;
+use crate::config::Config;
+use crate::emitter::{Emitter, EmitterShape};
+use crate::export::{Export, ExportSettings, VideoFormat};
+use crate::lighting::{Light, LightSource, Lighting};
+use crate::mesh::Mesh;
+use crate::mesh_grid::MeshGrid;
+use crate::palette::{ColorField, ColorMap, Palette};
+use crate::poke::{Poke, PokeSettings, PokeShape, RadiusUnit};
+use crate::preset::{Preset, Transition};
 use crate::renderer::{GfxState};
-use crate::sim_renderer::RenderMode;
+use crate::sim_renderer::{Antialiasing, PrismType, RenderConfig, RenderMode};
 use crate::simulation::WaveSimulation;
+use crate::raindrops::Raindrops;
+use crate::record::{InputEvent, Recorder, Recording, Replay, Settings};
+use crate::scene::{Region, Scene};
+use crate::screenshot::{ScreenshotScale, ScreenshotSettings};
+use crate::shader_watch::ShaderWatcher;
+use crate::snapshot::Snapshot;
+use crate::stroke::Stroke;
+use crate::solver::{SolverKind, WaveSolver};
 
-#[derive(Copy, Clone, PartialEq)]
-enum PrismType {
-    Square,
-    Hex,
-}
+const MIN_TIME_SCALE: f32 = 0.125;
+const MAX_TIME_SCALE: f32 = 8.0;
 
-#[derive(Copy, Clone, PartialEq)]
-struct RenderConfig {
-    prism_type: PrismType,
-    prism_height: f32,
-    grid_size: usize,
-    step_size: f32,
+#[derive(Copy, Clone)]
+enum FileAction {
+    Save,
+    Load,
 }
 
+impl FileAction {
+    fn past_tense(&self) -> &'static str {
+        match self {
+            FileAction::Save => "Saved",
+            FileAction::Load => "Loaded",
+        }
+    }
+}
 
 #[derive(Copy, Clone)]
-struct CameraConfig {
-    rotation_enabled: bool,
-    change_angle: bool,
-    rotation_speed: f32,
-    distance: f32,
-    angle: f32,
-    target_ratio: f32,
+enum RecordingAction {
+    Start,
+    Stop,
+    Replay,
+    StopReplay,
 }
 
 #[derive(Copy, Clone)]
-struct RaindropConfig {
-    enabled: bool,
-    delay: u32,
-    ticks: u32,
+enum PresetAction {
+    Save,
+    Delete,
+}
+
+/// Picked on the command line
+struct Options {
+    /// Solver to switch to once the GPU is up, instead of the one from the saved settings
+    solver: Option<SolverKind>,
+    /// Scene to set up once the window is up, before any snapshot is loaded
+    scene: Option<PathBuf>,
+    /// Snapshot to load once the window is up
+    load: Option<PathBuf>,
+    /// Where to save a snapshot when the window is closed
+    save_on_exit: Option<PathBuf>,
+    /// Seed for the raindrops, random if not given
+    seed: Option<u64>,
+    /// Records everything from startup and saves it here when the window is closed
+    record: Option<PathBuf>,
+    /// Recording to play back once the window is up
+    replay: Option<PathBuf>,
+    /// Inner size of the window in physical pixels
+    window_size: Option<UVec2>,
+    /// Borderless fullscreen on the current monitor
+    fullscreen: bool,
+    present_mode: Option<wgpu::PresentMode>,
+    // These override the saved settings and the scene
+    render_mode: Option<RenderMode>,
+    prism_type: Option<PrismType>,
+    grid_size: Option<usize>,
+    sim_size: Option<UVec2>,
+    /// Loads the render shaders from here instead of the built in ones and reloads them on save
+    shader_dir: Option<PathBuf>,
+}
+
+const USAGE: &str = "usage: ripple [--window-size WxH] [--fullscreen] [--present-mode MODE]
+              [--render-mode texture|prism] [--prism square|hexagon] [--grid-size N] [--sim-size WxH]
+              [--solver cpu|gpu] [--scene SCENE] [--load SNAPSHOT] [--save-on-exit SNAPSHOT] [--seed N]
+              [--record RECORDING | --replay RECORDING] [--shaders DIR]
+       ripple batch --help     run without a window
+       ripple export --help    render a clip without a window
+
+present modes: auto-vsync, auto-no-vsync, fifo, mailbox, immediate
+--shaders reloads pipeline_prism.wgsl and pipeline_2d.wgsl from DIR whenever they change,
+e.g. --shaders src/shaders";
+
+impl Options {
+    fn from_args() -> Result<Self, String> {
+        let mut options = Options {
+            solver: std::env::var("RIPPLE_SOLVER").ok()
+                .and_then(|name| SolverKind::from_name(&name)),
+            scene: None,
+            load: None,
+            save_on_exit: None,
+            seed: None,
+            record: None,
+            replay: None,
+            window_size: None,
+            fullscreen: false,
+            present_mode: None,
+            render_mode: None,
+            prism_type: None,
+            grid_size: None,
+            sim_size: None,
+            shader_dir: None,
+        };
+        let mut args = std::env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--solver" => {
+                    let name = value()?;
+                    options.solver = Some(SolverKind::from_name(&name)
+                        .ok_or_else(|| format!("unknown solver {}", name))?);
+                }
+                "--scene" => options.scene = Some(value()?.into()),
+                "--load" => options.load = Some(value()?.into()),
+                "--save-on-exit" => options.save_on_exit = Some(value()?.into()),
+                "--seed" => {
+                    let seed = value()?;
+                    options.seed = Some(seed.parse().map_err(|_| format!("bad seed {}", seed))?);
+                }
+                "--record" => options.record = Some(value()?.into()),
+                "--replay" => options.replay = Some(value()?.into()),
+                "--window-size" => options.window_size = Some(batch::parse_size(&value()?)?),
+                "--fullscreen" => options.fullscreen = true,
+                "--present-mode" => {
+                    let name = value()?;
+                    options.present_mode = Some(renderer::present_mode_from_name(&name)
+                        .ok_or_else(|| format!("unknown present mode {}", name))?);
+                }
+                "--render-mode" => {
+                    let name = value()?;
+                    options.render_mode = Some(RenderMode::from_name(&name)
+                        .ok_or_else(|| format!("unknown render mode {}", name))?);
+                }
+                "--prism" => {
+                    let name = value()?;
+                    options.prism_type = Some(PrismType::from_name(&name)
+                        .ok_or_else(|| format!("unknown prism {}", name))?);
+                }
+                "--grid-size" => {
+                    let size = value()?;
+                    let sizes = RenderConfig::GRID_SIZES;
+                    options.grid_size = Some(size.parse().ok().filter(|size| sizes.contains(size))
+                        .ok_or_else(|| format!("bad grid size {}, expected {} to {}", size, sizes.start(), sizes.end()))?);
+                }
+                "--sim-size" => options.sim_size = Some(batch::parse_sim_size(&value()?)?),
+                "--shaders" => options.shader_dir = Some(value()?.into()),
+                "--help" | "-h" => {
+                    println!("Interactive wave simulation\n{}", USAGE);
+                    std::process::exit(0);
+                }
+                _ => return Err(format!("unexpected argument {}", arg)),
+            }
+        }
+        Ok(options)
+    }
 }
 
 struct App<'a> {
     window: Option<Arc<Window>>,
     renderer: Option<GfxState<'a>>,
     rotation: f32,
-    simulation: WaveSimulation,
+    solver: Box<dyn WaveSolver>,
+    clock: SimClock,
+    /// Draw frames between ticks by blending the last two states
+    interpolate: bool,
+    /// How many ticks the step N action runs
+    step_count: u32,
+    /// Ticks run since startup, drives the emitters
+    tick: u64,
+    emitters: Vec<Emitter>,
+    /// Settings for the next emitter placed with the emitter tool
+    new_emitter: Emitter,
+    /// Where the line emitter being dragged out starts
+    emitter_line_start: Option<Vec2>,
+    options: Options,
+    reference_difference: Option<f32>,
+    /// Scene file picked in the settings window
+    scene_path: String,
+    /// Outcome of the last scene save or load, shown next to the buttons
+    scene_status: Option<String>,
+    /// Walls and medium the last scene was set up with, saved back out with the rest of it.
+    /// Painting with the brush doesn't change these.
+    regions: Vec<Region>,
+    /// Snapshot file picked in the settings window
+    snapshot_path: String,
+    /// Outcome of the last save or load, shown next to the buttons
+    snapshot_status: Option<String>,
+    /// Grid size being edited in the settings window, applied with the resize button
+    pending_sim_size: UVec2,
+    /// World space size of the prism grid, see MeshGrid::extent
+    grid_extent: Vec2,
     camera: Camera,
     render_config: RenderConfig,
+    /// The built in palettes, then any loaded from a file
+    palettes: Vec<Palette>,
+    /// Indexed by `RenderMode`
+    color_maps: [ColorMap; 2],
+    /// Palette file picked in the settings window
+    palette_path: String,
+    /// Last palette file loaded, loaded again on the next launch
+    palette_file: Option<PathBuf>,
+    palette_status: Option<String>,
     camera_config: CameraConfig,
-    raindrop_config: RaindropConfig,
+    lighting: Lighting,
+    /// Seed the raindrops were last started with
+    seed: u64,
+    raindrops: Raindrops,
+    recorder: Option<Recorder>,
+    replay: Option<Replay>,
+    /// Recording file picked in the settings window
+    recording_path: String,
+    /// Outcome of the last recording or replay, shown next to the buttons
+    recording_status: Option<String>,
+    /// Settings for the next export, the paths are filled in from `export_path` and `export_video`
+    export_settings: ExportSettings,
+    /// Folder picked in the settings window
+    export_path: String,
+    export_video: Option<VideoFormat>,
+    /// Takes over the clock while running, see `export_frame`
+    export: Option<Export>,
+    /// Camera rotation at the first exported frame
+    export_start_rotation: f32,
+    export_status: Option<String>,
+    screenshot: ScreenshotSettings,
+    /// Where the last screenshot went, or why it failed
+    screenshot_status: Option<String>,
+    /// Presets the user saved, listed after the built in ones
+    user_presets: Vec<Preset>,
+    /// Name the current settings get saved under
+    preset_name: String,
+    /// Seconds, see `Config::preset_transition`
+    preset_transition_seconds: f32,
+    preset_transition: Option<Transition>,
+    preset_status: Option<String>,
+    shader_watcher: Option<ShaderWatcher>,
+    /// Why the last reload of each shader failed, shown over the window until it compiles
+    shader_errors: Vec<(RenderMode, String)>,
     mouse_position: Vec2,
+    mouse_down: bool,
+    brush: Brush,
+    /// What clicking with the poke tool does
+    poke: PokeSettings,
+    /// Drag with the poke tool currently being drawn through the water
+    stroke: Stroke,
     show_settings: bool,
 }
 
 impl App<'_> {
-    pub fn new() -> Self {
+    pub fn new(options: Options) -> Self {
+        let seed = options.seed.unwrap_or_else(rand::random);
+        info!("Raindrop seed: {}", seed);
         let camera = Camera::new(vec3(0.0, 0.0, -1.0), vec3(0.0, 0.0, 0.0));
+        let shader_watcher = options.shader_dir.clone().map(ShaderWatcher::new);
         Self {
             window: None,
             renderer: None,
             rotation: 0.0,
-            simulation: WaveSimulation::new(simulation::DIVISIONS),
+            solver: Box::new(WaveSimulation::new(simulation::DEFAULT_SIZE)),
+            clock: SimClock::default(),
+            interpolate: true,
+            step_count: 10,
+            tick: 0,
+            emitters: Vec::new(),
+            new_emitter: Emitter::default(),
+            emitter_line_start: None,
+            options,
+            snapshot_path: "snapshot.ripple".into(),
+            snapshot_status: None,
+            scene_path: "scene.ron".into(),
+            scene_status: None,
+            regions: Vec::new(),
+            reference_difference: None,
+            pending_sim_size: simulation::DEFAULT_SIZE,
+            grid_extent: Vec2::ONE,
             mouse_position: Vec2::ZERO,
-            render_config: RenderConfig {
-                prism_type: PrismType::Hex,
-                prism_height: 5.0,
-                grid_size: 16,
-                step_size: 1.0,
-            },
-            camera_config: CameraConfig {
-                rotation_enabled: true,
-                change_angle: false,
-                rotation_speed: 0.10,
-                distance: 1.1,
-                angle: 20.0,
-                target_ratio: 0.0,
-            },
-            raindrop_config: RaindropConfig {
-                enabled: true,
-                delay: 250,
-                ticks: 0,
-            },
+            mouse_down: false,
+            brush: Brush::default(),
+            poke: PokeSettings::default(),
+            stroke: Stroke::default(),
+            render_config: RenderConfig::default(),
+            palettes: palette::built_in(),
+            color_maps: RenderMode::ALL.map(ColorMap::default_for),
+            palette_path: "palettes.ron".into(),
+            palette_file: None,
+            palette_status: None,
+            camera_config: CameraConfig::default(),
+            lighting: Lighting::default(),
+            seed,
+            raindrops: Raindrops::new(seed),
+            recorder: None,
+            replay: None,
+            recording_path: "recording.ripple-rec".into(),
+            recording_status: None,
+            export_settings: ExportSettings::default(),
+            export_path: "export".into(),
+            export_video: Some(VideoFormat::Gif),
+            export: None,
+            export_start_rotation: 0.0,
+            export_status: None,
+            screenshot: ScreenshotSettings::default(),
+            screenshot_status: None,
+            user_presets: Vec::new(),
+            preset_name: "My preset".into(),
+            preset_transition_seconds: 1.0,
+            preset_transition: None,
+            preset_status: None,
+            shader_watcher,
+            shader_errors: Vec::new(),
             camera,
             show_settings: true,
         }
@@ -103,32 +373,410 @@ impl App<'_> {
     fn initialize(&mut self, event_loop: &ActiveEventLoop) {
         let mut attributes = Window::default_attributes();
         attributes.title = "Ripple".into();
+        if let Some(size) = self.options.window_size {
+            attributes.inner_size = Some(PhysicalSize::new(size.x, size.y).into());
+        }
+        if self.options.fullscreen {
+            attributes.fullscreen = Some(Fullscreen::Borderless(None));
+        }
         let window = Arc::new(event_loop.create_window(attributes).unwrap());
         self.window = Some(window.clone());
 
-        let (mesh, grid) = match self.render_config.prism_type {
-            PrismType::Square => {
-                let mesh = mesh::square_prism(self.render_config.prism_height);
-                let grid = mesh_grid::MeshGrid::square_grid(self.render_config.grid_size, self.render_config.step_size);
-                (mesh, grid)
-            }
-            PrismType::Hex => {
-                let mesh = mesh::hex_prism(self.render_config.prism_height);
-                let grid = mesh_grid::MeshGrid::hex_grid(self.render_config.grid_size, self.render_config.step_size);
-                (mesh, grid)
-            }
-        };
+        let (mesh, grid) = self.build_prism();
+        self.grid_extent = grid.extent;
         let state = pollster::block_on(
             GfxState::new(
-                window.clone(), 60f32.to_radians(), &mesh, &grid)
+                window.clone(), self.options.present_mode, 60f32.to_radians(), &mesh, &grid, self.solver.size())
         );
         self.renderer = Some(state);
+        match Config::load() {
+            Ok(Some(config)) => self.apply_config(&config),
+            Ok(None) => {}
+            // The app already starts out with `Config::default()`
+            Err(error) => log::warn!("Couldn't read the saved settings, using the defaults: {}", error),
+        }
+        match preset::load_user() {
+            Ok(presets) => self.user_presets = presets,
+            Err(error) => log::warn!("Couldn't read the saved presets: {}", error),
+        }
+        if let Some(kind) = self.options.solver {
+            self.set_solver(kind);
+        }
+        if let Some(path) = self.options.scene.clone() {
+            if let Err(error) = self.load_scene(&path) {
+                log::error!("Couldn't load scene {}: {}", path.display(), error);
+            }
+        }
+        self.apply_options();
+        if let Some(path) = self.options.load.clone() {
+            if let Err(error) = self.load_snapshot(&path) {
+                log::error!("Couldn't load snapshot {}: {}", path.display(), error);
+            }
+        }
+        if let Some(path) = self.options.replay.clone() {
+            if let Err(error) = self.start_replay(&path) {
+                log::error!("Couldn't load recording {}: {}", path.display(), error);
+            }
+        } else if self.options.record.is_some() {
+            self.start_recording();
+        }
+    }
+
+    /// Applies the settings given on the command line on top of the saved ones and the scene
+    fn apply_options(&mut self) {
+        if let Some(mode) = self.options.render_mode {
+            self.renderer.as_mut().unwrap().sim.render_mode = mode;
+        }
+        if let Some(size) = self.options.sim_size {
+            self.apply_input(InputEvent::Resize(size));
+        }
+        let mut config = self.render_config;
+        config.prism_type = self.options.prism_type.unwrap_or(config.prism_type);
+        config.grid_size = self.options.grid_size.unwrap_or(config.grid_size);
+        if config != self.render_config {
+            self.render_config = config;
+            self.rebuild_prism();
+        }
+    }
+
+    fn build_prism(&self) -> (Mesh, MeshGrid) {
+        self.render_config.build(self.solver.size())
+    }
+
+    fn rebuild_prism(&mut self) {
+        let (mesh, grid) = self.build_prism();
+        self.grid_extent = grid.extent;
+        let renderer = self.renderer.as_mut().unwrap();
+        renderer.sim.update_prism(&renderer.device, &mesh);
+        renderer.sim.update_grid(&renderer.device, &grid);
+    }
+
+    /// Resamples the field onto a grid of the new size and rebuilds everything sized to match
+    fn resize_simulation(&mut self, size: UVec2) {
+        if self.solver.size() == size {
+            return;
+        }
+        self.solver.resize(size);
+        let renderer = self.renderer.as_mut().unwrap();
+        renderer.sim.resize_sim(&renderer.device, size);
+        self.rebuild_prism();
+    }
+
+    fn save_snapshot(&self, path: &Path) -> std::io::Result<()> {
+        Snapshot::capture(self.solver.as_ref(), self.tick).save(path)?;
+        info!("Saved snapshot to {}", path.display());
+        Ok(())
+    }
+
+    fn load_snapshot(&mut self, path: &Path) -> std::io::Result<()> {
+        let snapshot = Snapshot::load(path)?;
+        if self.recorder.take().is_some() {
+            log::warn!("Loading a snapshot stopped the recording");
+        }
+        self.replay = None;
+        self.resize_simulation(snapshot.size);
+        self.pending_sim_size = snapshot.size;
+        snapshot.restore(self.solver.as_mut());
+        self.tick = snapshot.tick;
+        info!("Loaded snapshot from {}", path.display());
+        Ok(())
+    }
+
+    /// The current setup as a scene, with the regions it was loaded with
+    fn scene(&self) -> Scene {
+        Scene {
+            size: self.solver.size(),
+            params: *self.solver.params(),
+            regions: self.regions.clone(),
+            emitters: self.emitters.clone(),
+            raindrops: self.raindrops.settings,
+            camera: self.camera_config,
+            render: self.render_config,
+            lighting: self.lighting.clone(),
+        }
+    }
+
+    fn save_scene(&self, path: &Path) -> std::io::Result<()> {
+        self.scene().save(path)?;
+        info!("Saved scene to {}", path.display());
+        Ok(())
+    }
+
+    /// Starts over from calm water with everything the scene describes
+    fn load_scene(&mut self, path: &Path) -> std::io::Result<()> {
+        let scene = Scene::load(path)?;
+        if self.recorder.take().is_some() {
+            log::warn!("Loading a scene stopped the recording");
+        }
+        self.replay = None;
+        self.resize_simulation(scene.size);
+        self.pending_sim_size = scene.size;
+        scene.apply_field(self.solver.as_mut());
+        self.emitters = scene.emitters;
+        self.raindrops.settings = scene.raindrops;
+        self.camera_config = scene.camera;
+        self.render_config = scene.render;
+        self.lighting = scene.lighting;
+        self.regions = scene.regions;
+        self.rebuild_prism();
+        info!("Loaded scene from {}", path.display());
+        Ok(())
+    }
+
+    /// The settings window as it is now, see `Config`
+    fn config(&self) -> Config {
+        Config {
+            solver: self.solver.kind(),
+            sim_size: self.solver.size(),
+            params: *self.solver.params(),
+            ticks_per_second: self.clock.ticks_per_second,
+            max_substeps: self.clock.max_substeps,
+            time_scale: self.clock.time_scale,
+            interpolate: self.interpolate,
+            step_count: self.step_count,
+            brush: self.brush,
+            poke: self.poke,
+            new_emitter: self.new_emitter,
+            raindrops: self.raindrops.settings,
+            render_mode: self.renderer.as_ref().unwrap().sim.render_mode,
+            render: self.render_config,
+            antialiasing: self.renderer.as_ref().unwrap().antialiasing(),
+            color_maps: self.color_maps.clone(),
+            palette_file: self.palette_file.clone(),
+            camera: self.camera_config,
+            lighting: self.lighting.clone(),
+            screenshot: self.screenshot.clone(),
+            preset_transition: self.preset_transition_seconds,
+            show_settings: self.show_settings,
+        }
+    }
+
+    fn apply_config(&mut self, config: &Config) {
+        self.apply_input(InputEvent::SetSolver(config.solver));
+        self.apply_input(InputEvent::Resize(config.sim_size));
+        if self.replay.is_none() {
+            *self.solver.params_mut() = config.params;
+        }
+        self.clock.ticks_per_second = config.ticks_per_second;
+        self.clock.max_substeps = config.max_substeps;
+        self.clock.time_scale = config.time_scale;
+        self.interpolate = config.interpolate;
+        self.step_count = config.step_count;
+        self.brush = config.brush;
+        self.poke = config.poke;
+        self.new_emitter = config.new_emitter;
+        self.raindrops.settings = config.raindrops;
+        self.renderer.as_mut().unwrap().sim.render_mode = config.render_mode;
+        self.renderer.as_mut().unwrap().set_antialiasing(config.antialiasing);
+        self.render_config = config.render;
+        self.camera_config = config.camera;
+        self.lighting = config.lighting.clone();
+        self.screenshot = config.screenshot.clone();
+        self.preset_transition_seconds = config.preset_transition;
+        self.show_settings = config.show_settings;
+        self.rebuild_prism();
+        self.palettes = palette::built_in();
+        self.palette_file = None;
+        if let Some(path) = &config.palette_file {
+            if let Err(error) = self.load_palettes(path) {
+                log::warn!("Couldn't load the palettes from {}: {}", path.display(), error);
+            }
+        }
+        self.color_maps = config.color_maps.clone();
+        self.update_color_maps();
+    }
+
+    /// Adds the palettes in `path`, replacing any with the same names. Returns how many there were.
+    fn load_palettes(&mut self, path: &Path) -> std::io::Result<usize> {
+        let loaded = palette::load(path)?;
+        let count = loaded.len();
+        for palette in loaded {
+            match self.palettes.iter_mut().find(|existing| existing.name == palette.name) {
+                Some(existing) => *existing = palette,
+                None => self.palettes.push(palette),
+            }
+        }
+        self.palette_file = Some(path.into());
+        self.update_color_maps();
+        Ok(count)
+    }
+
+    /// Sends the palettes picked for each render mode to the GPU
+    fn update_color_maps(&self) {
+        let renderer = self.renderer.as_ref().unwrap();
+        for mode in RenderMode::ALL {
+            let color_map = &self.color_maps[mode as usize];
+            let palette = color_map.find_palette(&self.palettes).unwrap_or(&self.palettes[0]);
+            renderer.sim.set_color_map(&renderer.queue, mode, palette, color_map.field, color_map.range);
+        }
+    }
+
+    /// The current look and behaviour, saved under `name`
+    fn preset(&self, name: &str) -> Preset {
+        Preset {
+            name: name.into(),
+            params: *self.solver.params(),
+            raindrops: self.raindrops.settings,
+            render_mode: self.renderer.as_ref().unwrap().sim.render_mode,
+            render: self.render_config,
+            camera: self.camera_config,
+        }
+    }
+
+    fn apply_preset(&mut self, preset: &Preset) {
+        self.apply_preset_blend(preset);
+        if self.render_config != preset.render {
+            self.render_config = preset.render;
+            self.rebuild_prism();
+        }
+    }
+
+    /// The part of `preset` that's cheap enough to change every frame of a transition
+    fn apply_preset_blend(&mut self, preset: &Preset) {
+        // A replay sets the params from the recording
+        if self.replay.is_none() {
+            *self.solver.params_mut() = preset.params;
+        }
+        self.raindrops.settings = preset.raindrops;
+        self.renderer.as_mut().unwrap().sim.render_mode = preset.render_mode;
+        self.camera_config = preset.camera;
+    }
+
+    /// Switches to `preset`, blending over `preset_transition_seconds` if that's set
+    fn pick_preset(&mut self, preset: Preset) {
+        if self.preset_transition_seconds > 0.0 {
+            let from = self.preset(&preset.name);
+            self.preset_transition = Some(Transition::new(from, preset, self.preset_transition_seconds));
+        } else {
+            self.preset_transition = None;
+            self.apply_preset(&preset);
+        }
+    }
+
+    fn update_preset_transition(&mut self) {
+        let Some(transition) = &mut self.preset_transition else {
+            return;
+        };
+        let preset = transition.advance(self.clock.delta());
+        if transition.is_finished() {
+            self.preset_transition = None;
+            self.apply_preset(&preset);
+        } else {
+            self.apply_preset_blend(&preset);
+        }
+    }
+
+    /// Saves the current settings as a user preset, replacing any with the same name
+    fn save_preset(&mut self) -> std::io::Result<PathBuf> {
+        let preset = self.preset(&self.preset_name);
+        match self.user_presets.iter_mut().find(|saved| saved.name == preset.name) {
+            Some(saved) => *saved = preset,
+            None => self.user_presets.push(preset),
+        }
+        preset::save_user(&self.user_presets)
+    }
+
+    fn settings(&self) -> Settings {
+        Settings {
+            params: *self.solver.params(),
+            emitters: self.emitters.clone(),
+        }
+    }
+
+    /// Applies something the user did, recording it if a recording is running
+    fn apply_input(&mut self, event: InputEvent) {
+        if self.replay.is_some() {
+            // The replay is in charge, anything else would make it diverge
+            return;
+        }
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(self.tick, event.clone());
+        }
+        self.apply_event(event);
+    }
+
+    fn apply_event(&mut self, event: InputEvent) {
+        match event {
+            InputEvent::Poke { poke, center } => self.solver.poke(&poke, center),
+            InputEvent::Paint { brush, center } => self.solver.paint(&brush, center),
+            InputEvent::ClearMedium => self.solver.clear_medium(),
+            InputEvent::Reset => self.solver.reset(),
+            InputEvent::Resize(size) => {
+                self.resize_simulation(size);
+                self.pending_sim_size = size;
+            }
+            InputEvent::SetSolver(kind) => self.set_solver(kind),
+            InputEvent::Settings(settings) => {
+                *self.solver.params_mut() = settings.params;
+                self.emitters = settings.emitters;
+            }
+        }
+    }
+
+    fn start_recording(&mut self) {
+        self.replay = None;
+        self.recorder = Some(Recorder::start(self.solver.as_ref(), self.tick, self.settings()));
+        self.recording_status = Some(format!("Recording from tick {}", self.tick));
+    }
+
+    fn stop_recording(&mut self, path: &Path) -> std::io::Result<()> {
+        let Some(recorder) = self.recorder.take() else {
+            return Ok(());
+        };
+        let recording = recorder.finish(self.solver.as_ref(), self.tick);
+        recording.save(path)?;
+        info!(
+            "Saved recording of {} events over {} ticks to {}",
+            recording.events.len(), recording.end_tick - recording.start.tick, path.display(),
+        );
+        Ok(())
+    }
+
+    /// Puts the recorded starting field back and plays the events on top of it as the clock ticks
+    fn start_replay(&mut self, path: &Path) -> std::io::Result<()> {
+        let recording = Recording::load(path)?;
+        self.recorder = None;
+        self.set_solver(recording.solver);
+        self.resize_simulation(recording.start.size);
+        self.pending_sim_size = recording.start.size;
+        recording.start.restore(self.solver.as_mut());
+        self.tick = recording.start.tick;
+        self.recording_status = Some(format!(
+            "Replaying ticks {} to {}", recording.start.tick, recording.end_tick,
+        ));
+        self.replay = Some(Replay::new(recording));
+        Ok(())
+    }
+
+    /// Checks the field against the recording once the replay has caught up with it
+    fn finish_replay(&mut self) {
+        let Some(replay) = self.replay.take() else {
+            return;
+        };
+        let status = if replay.matches(self.solver.as_ref()) {
+            format!("Replay finished at tick {}, field matches the recording", self.tick)
+        } else {
+            format!("Replay finished at tick {}, field differs from the recording", self.tick)
+        };
+        info!("{}", status);
+        self.recording_status = Some(status);
+        // Hold the final field so it can be inspected
+        self.clock.paused = true;
+    }
+
+    /// Swaps in a different solver, carrying the current field over so the water doesn't reset
+    fn set_solver(&mut self, kind: SolverKind) {
+        if self.solver.kind() == kind {
+            return;
+        }
+        let renderer = self.renderer.as_ref().unwrap();
+        self.solver = solver::switch_solver(self.solver.as_ref(), kind, &renderer.device, &renderer.queue);
     }
 
     fn input(&mut self, event: &WindowEvent) -> bool {
         let renderer = self.renderer.as_mut().unwrap();
-        let window = renderer.window.clone();
-        renderer.egui_renderer.handle_input(&window, event);
+        let window = self.window.clone().unwrap();
+        let egui_consumed = renderer.egui_renderer().handle_input(&window, event);
 
         match event {
             WindowEvent::CursorMoved { position, .. } => {
@@ -137,28 +785,23 @@ impl App<'_> {
                     position.x as f32 / size.width as f32 * 2.0 - 1.0,
                     -1.0 * (position.y as f32 / size.height as f32 * 2.0 - 1.0),
                 );
-            }
-            WindowEvent::MouseInput { state: Pressed, .. } => {
-                let result = camera::project_screen_onto_plane(self.mouse_position, Plane3::ZX,
-                                                               renderer.projection.calc_matrix(), self.camera.calc_matrix());
-                if let Some(intersection) = result {
-                    let plane_point = intersection.xz();
-                    println!("mouse click result: {}", plane_point);
-                    if self.render_config.prism_type == PrismType::Square {
-                        let size = self.render_config.grid_size as f32 * self.render_config.step_size;
-                        let normalized = (plane_point + (size / 2.0)) / size;
-                        println!("normalized: {}", normalized);
-                        self.simulation.poke_normalized(normalized);
-                    } else {
-                        let hexes = self.render_config.grid_size as f32 * 2.0 + 1.0;
-                        let grid_width = hexes * self.render_config.step_size * 3.0_f32.sqrt() * 0.5;
-                        println!("grid width: {}", grid_width);
-                        let normalized = (plane_point + (grid_width / 2.0)) / grid_width;
-                        println!("normalized: {}", normalized);
-                        self.simulation.poke_normalized(normalized);
+                if self.mouse_down {
+                    match self.brush.tool {
+                        BrushTool::Poke => self.continue_stroke(),
+                        BrushTool::Emitter => {}
+                        _ => self.apply_brush(),
                     }
                 }
             }
+            WindowEvent::MouseInput { state: Pressed, .. } if !egui_consumed => {
+                self.mouse_down = true;
+                self.apply_brush();
+            }
+            WindowEvent::MouseInput { state: Released, .. } => {
+                self.mouse_down = false;
+                self.stroke.end();
+                self.finish_line_emitter();
+            }
             WindowEvent::KeyboardInput {
                 event: KeyEvent {
                     physical_key,
@@ -166,7 +809,7 @@ impl App<'_> {
                     repeat: false,
                     ..
                 }, ..
-            } => {
+            } if !egui_consumed => {
                 match physical_key {
                     PhysicalKey::Code(KeyCode::Space) => {
                         let renderer = self.renderer.as_mut().unwrap();
@@ -176,6 +819,13 @@ impl App<'_> {
                         }
                     }
                     PhysicalKey::Code(KeyCode::KeyS) => self.show_settings = !self.show_settings,
+                    PhysicalKey::Code(KeyCode::KeyP) => self.clock.paused = !self.clock.paused,
+                    PhysicalKey::Code(KeyCode::Period) => self.clock.step(1),
+                    PhysicalKey::Code(KeyCode::KeyN) => self.clock.step(self.step_count),
+                    PhysicalKey::Code(KeyCode::BracketLeft) => self.clock.time_scale = (self.clock.time_scale * 0.5).max(MIN_TIME_SCALE),
+                    PhysicalKey::Code(KeyCode::BracketRight) => self.clock.time_scale = (self.clock.time_scale * 2.0).min(MAX_TIME_SCALE),
+                    PhysicalKey::Code(KeyCode::KeyR) => self.apply_input(InputEvent::Reset),
+                    PhysicalKey::Code(KeyCode::F12) => self.take_screenshot(),
                     _ => {}
                 }
             }
@@ -184,10 +834,118 @@ impl App<'_> {
         return false;
     }
 
+    /// Maps the cursor onto the simulation grid, in the 0..1 range
+    fn cursor_to_normalized(&self) -> Option<Vec2> {
+        let renderer = self.renderer.as_ref().unwrap();
+        match renderer.sim.render_mode {
+            RenderMode::Texture => {
+                // The texture view fills the window, with the first row at the top
+                Some((vec2(self.mouse_position.x, -self.mouse_position.y) + 1.0) * 0.5)
+            }
+            RenderMode::Prism => {
+                let result = camera::project_screen_onto_plane(self.mouse_position, Plane3::ZX,
+                                                               renderer.projection.calc_matrix(), self.camera.calc_matrix());
+                let plane_point = result?.xz();
+                Some((plane_point + self.grid_extent / 2.0) / self.grid_extent)
+            }
+        }
+    }
+
+    fn apply_brush(&mut self) {
+        let Some(normalized) = self.cursor_to_normalized() else {
+            return;
+        };
+        match self.brush.tool {
+            BrushTool::Poke => {
+                let poke = self.poke.poke(self.cells_per_world_unit());
+                let center = normalized.clamp(Vec2::ZERO, Vec2::ONE) * self.solver.size().as_vec2();
+                self.apply_input(InputEvent::Poke { poke, center });
+                self.stroke.begin(normalized * self.solver.size().as_vec2());
+            }
+            BrushTool::Emitter => self.place_emitter(normalized),
+            _ => {
+                let center = normalized * self.solver.size().as_vec2();
+                self.apply_input(InputEvent::Paint { brush: self.brush, center });
+            }
+        }
+    }
+
+    /// Pokes along the path the cursor took since the last sample, harder the faster it moves
+    fn continue_stroke(&mut self) {
+        // The cursor can leave the water in the prism view, pick the stroke back up when it returns
+        let Some(normalized) = self.cursor_to_normalized()
+            .filter(|point| point.cmpge(Vec2::ZERO).all() && point.cmple(Vec2::ONE).all()) else {
+            self.stroke.end();
+            return;
+        };
+        let position = normalized * self.solver.size().as_vec2();
+        if !self.stroke.is_active() {
+            self.stroke.begin(position);
+            return;
+        }
+        let poke = self.poke.poke(self.cells_per_world_unit());
+        for (center, scale) in self.stroke.extend(position, poke.radius * 0.5) {
+            let scaled = Poke {
+                amplitude: poke.amplitude * scale,
+                ..poke
+            };
+            self.apply_input(InputEvent::Poke { poke: scaled, center });
+        }
+    }
+
+    /// Converts world space sizes from the 3D view into cells
+    fn cells_per_world_unit(&self) -> f32 {
+        self.solver.size().x as f32 / self.grid_extent.x
+    }
+
+    /// Points go down straight away, lines are dragged out and placed on release
+    fn place_emitter(&mut self, position: Vec2) {
+        if self.emitters.len() >= solver::MAX_SOURCES || self.replay.is_some() {
+            return;
+        }
+        let position = position.clamp(Vec2::ZERO, Vec2::ONE);
+        match self.new_emitter.shape {
+            EmitterShape::Point => self.emitters.push(Emitter {
+                start: position,
+                end: position,
+                ..self.new_emitter
+            }),
+            EmitterShape::Line => self.emitter_line_start = Some(position),
+        }
+    }
+
+    fn finish_line_emitter(&mut self) {
+        let Some(start) = self.emitter_line_start.take() else {
+            return;
+        };
+        if let Some(end) = self.cursor_to_normalized() {
+            self.emitters.push(Emitter {
+                start,
+                end: end.clamp(Vec2::ZERO, Vec2::ONE),
+                ..self.new_emitter
+            });
+        }
+    }
+
     fn handle_next_frame(&mut self, event_loop: &ActiveEventLoop) {
+        let ticks = self.clock.update();
+        self.reload_shaders();
+        self.update_preset_transition();
         self.update_camera();
-        self.tick_raindrops();
-        self.simulation.advance();
+        let settings = self.settings();
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record_settings(self.tick, &settings);
+        }
+        if self.export.is_some() {
+            // Exports run at a fixed number of ticks per frame, however long frames take
+            self.export_frame();
+        } else {
+            for _ in 0..ticks {
+                if !self.run_tick() {
+                    break;
+                }
+            }
+        }
         if !self.render() {
             event_loop.exit();
         } else {
@@ -195,89 +953,476 @@ impl App<'_> {
         }
     }
 
-    fn update_camera(&mut self) {
-        let grid_width = match self.render_config.prism_type {
-            PrismType::Square => {
-                self.render_config.grid_size as f32 * self.render_config.step_size
+    /// Advances the simulation by one tick, returns false if a replay ended instead
+    fn run_tick(&mut self) -> bool {
+        if let Some(mut replay) = self.replay.take() {
+            let Ok(running) = replay.step(self.tick, |event| {
+                self.apply_event(event);
+                Ok::<_, Infallible>(())
+            });
+            self.replay = Some(replay);
+            if !running {
+                self.finish_replay();
+                return false;
             }
-            PrismType::Hex => {
-                self.render_config.grid_size as f32 * 2.0 * self.render_config.step_size * 3_f32.sqrt() * 0.5
+        } else {
+            self.tick_raindrops();
+        }
+        let sources = emitter::sources(&self.emitters, self.tick, self.solver.size());
+        self.solver.set_sources(&sources);
+        self.solver.advance();
+        self.tick += 1;
+        true
+    }
+
+    fn start_export(&mut self) {
+        let directory = PathBuf::from(&self.export_path);
+        let settings = ExportSettings {
+            video: self.export_video.map(|format| directory.join(format!("clip.{}", format.extension()))),
+            directory,
+            ..self.export_settings.clone()
+        };
+        match Export::start(settings) {
+            Ok(export) => {
+                self.export_start_rotation = self.rotation;
+                self.export = Some(export);
+                self.export_status = None;
             }
+            Err(error) => self.export_status = Some(format!("Error: {}", error)),
+        }
+    }
+
+    /// Saves the field as it's drawn in the window, without the settings window on top
+    fn take_screenshot(&mut self) {
+        let scale = self.screenshot.scale.factor();
+        let renderer = self.renderer.as_mut().unwrap();
+        let size = UVec2::new(renderer.size.width, renderer.size.height) * scale;
+        if size.max_element() > renderer.device.limits().max_texture_dimension_2d {
+            self.screenshot_status = Some(format!("Error: {}x{} is too big for this GPU", size.x, size.y));
+            return;
+        }
+        let alpha = if self.interpolate { self.clock.alpha() } else { 1.0 };
+        renderer.update_field(self.solver.output(), alpha);
+        let mut frame = renderer.capture(size, &self.camera);
+        if self.screenshot.downsample && scale > 1 {
+            frame = frame.downsample(scale);
+        }
+        let path = self.screenshot.path(SystemTime::now());
+        let result = std::fs::create_dir_all(&self.screenshot.directory)
+            .and_then(|()| frame.save_png(&path));
+        self.screenshot_status = Some(match result {
+            Ok(()) => {
+                info!("Saved screenshot to {}", path.display());
+                format!("Saved {}", path.display())
+            }
+            Err(error) => format!("Error: {}", error),
+        });
+    }
+
+    /// Rebuilds the pipelines for any shaders that changed on disk, see `ShaderWatcher`
+    fn reload_shaders(&mut self) {
+        let Some(watcher) = &mut self.shader_watcher else {
+            return;
+        };
+        let renderer = self.renderer.as_mut().unwrap();
+        for (mode, source) in watcher.poll() {
+            let result = source
+                .map_err(|error| format!("Couldn't read it: {}", error))
+                .and_then(|source| renderer.sim.reload_shader(&renderer.device, mode, &source));
+            self.shader_errors.retain(|(failed, _)| *failed != mode);
+            match result {
+                Ok(()) => info!("Reloaded {}", mode.shader_file()),
+                Err(error) => {
+                    log::warn!("Couldn't reload {}: {}", mode.shader_file(), error);
+                    self.shader_errors.push((mode, error));
+                }
+            }
+        }
+    }
+
+    /// Writes the next frame of the running export, then steps the simulation on to the one after
+    fn export_frame(&mut self) {
+        let Some(export) = &self.export else {
+            return;
         };
-        let half_width = grid_width * 0.5;
+        self.rotation = export.settings.rotation(self.export_start_rotation, export.written());
+        self.camera = self.camera_config.orbit(self.rotation, self.grid_extent);
+        let renderer = self.renderer.as_mut().unwrap();
+        renderer.update_field(self.solver.output(), 1.0);
+        let frame = renderer.capture(export.settings.size, &self.camera);
 
-        if self.camera_config.rotation_enabled {
-            self.rotation += self.camera_config.rotation_speed.to_radians();
+        let export = self.export.as_mut().unwrap();
+        if let Err(error) = export.add_frame(&frame) {
+            self.export_status = Some(format!("Error: {}", error));
+            self.export = None;
+            return;
+        }
+        if export.is_finished() {
+            let export = self.export.take().unwrap();
+            let directory = export.settings.directory.clone();
+            let frames = export.written();
+            self.export_status = Some(match export.finish() {
+                Ok(()) => format!("Wrote {} frames to {}", frames, directory.display()),
+                Err(error) => format!("Error: {}", error),
+            });
+            return;
+        }
+        for _ in 0..export.settings.ticks_per_frame {
+            if !self.run_tick() {
+                break;
+            }
+        }
+    }
+
+    fn update_camera(&mut self) {
+        if self.export.is_some() {
+            // export_frame moves the camera
+            return;
         }
-        let pos = vec3(self.rotation.sin(), 0.0, -self.rotation.cos()) * half_width * self.camera_config.distance;
-        self.camera.target = pos * self.camera_config.target_ratio;
-        self.camera.position = pos;
-        let mut angle = self.camera_config.angle.to_radians();
-        if self.camera_config.change_angle {
-            angle = angle * (0.6 + 0.4 * self.rotation.sin());
+        if self.camera_config.rotation_enabled {
+            // The speed is in degrees per frame at 60 fps
+            self.rotation += self.camera_config.rotation_speed.to_radians() * self.clock.delta() * 60.0;
         }
-        self.camera.position.y = angle.sin() * half_width;
+        self.camera = self.camera_config.orbit(self.rotation, self.grid_extent);
     }
 
     fn tick_raindrops(&mut self) {
-        if self.raindrop_config.enabled {
-            self.raindrop_config.ticks += 1;
-            if self.raindrop_config.ticks >= self.raindrop_config.delay {
-                self.raindrop_config.ticks = 0;
-                self.simulation.poke_normalized(vec2(
-                    rand::random(),
-                    rand::random(),
-                ));
-            }
+        if let Some(position) = self.raindrops.tick() {
+            let poke = self.raindrops.settings.poke.poke(self.cells_per_world_unit());
+            let center = position * self.solver.size().as_vec2();
+            self.apply_input(InputEvent::Poke { poke, center });
         }
     }
 
     pub fn render_ui(&mut self) {
+        let mut solver_kind = self.solver.kind();
+        let mut compare_with_reference = false;
+        let mut resize_to = None;
+        let mut snapshot_action = None;
+        let mut scene_action = None;
+        let mut recording_action = None;
+        let mut start_export = false;
+        let mut cancel_export = false;
+        let mut take_screenshot = false;
+        let mut restore_defaults = false;
+        let built_in_presets = preset::built_in();
+        let mut picked_preset = None;
+        let mut preset_action = None;
+        let mut inputs = Vec::new();
+        let mut config = self.render_config;
+        let mut color_maps = self.color_maps.clone();
+        let mut load_palettes = false;
+        // Editors that change the solver directly are locked while a replay is in charge
+        let replaying = self.replay.is_some();
         let renderer = self.renderer.as_mut().unwrap();
+        let mut antialiasing = renderer.antialiasing();
+        let supported_antialiasing = renderer.supported_antialiasing().to_vec();
         egui::Window::new("Settings")
             .resizable(true)
             .vscroll(true)
             .default_open(false)
             .open(&mut self.show_settings)
-            .show(renderer.egui_renderer.context(), |ui| {
+            .show(renderer.egui_renderer().context(), |ui| {
+                if ui.button("Restore defaults").clicked() {
+                    restore_defaults = true;
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Preset");
+                    egui::ComboBox::from_id_salt("presets")
+                        .selected_text("Pick")
+                        .show_ui(ui, |ui| {
+                            for preset in built_in_presets.iter().chain(&self.user_presets) {
+                                if ui.selectable_label(false, &preset.name).clicked() {
+                                    picked_preset = Some(preset.clone());
+                                }
+                            }
+                        });
+                    egui::DragValue::new(&mut self.preset_transition_seconds)
+                        .range(0.0..=10.0)
+                        .speed(0.05)
+                        .prefix("Blend over ")
+                        .suffix(" s")
+                        .ui(ui);
+                });
+                ui.horizontal(|ui| {
+                    egui::TextEdit::singleline(&mut self.preset_name).desired_width(160.0).ui(ui);
+                    if ui.button("Save preset").clicked() {
+                        preset_action = Some(PresetAction::Save);
+                    }
+                    if self.user_presets.iter().any(|preset| preset.name == self.preset_name)
+                        && ui.button("Delete").clicked() {
+                        preset_action = Some(PresetAction::Delete);
+                    }
+                });
+                if let Some(status) = &self.preset_status {
+                    ui.label(status);
+                }
                 ui.label("Simulation");
-                ui.add(egui::Slider::new(&mut self.simulation.damping, 0.9..=1.0).fixed_decimals(3).text("Damping"));
+                ui.horizontal(|ui| {
+                    let label = if self.clock.paused { "Play (P)" } else { "Pause (P)" };
+                    if ui.button(label).clicked() {
+                        self.clock.paused = !self.clock.paused;
+                    }
+                    if ui.button("Step (.)").clicked() {
+                        self.clock.step(1);
+                    }
+                    if ui.button(format!("Step {} (N)", self.step_count)).clicked() {
+                        self.clock.step(self.step_count);
+                    }
+                    egui::DragValue::new(&mut self.step_count).range(1..=1000).ui(ui);
+                });
+                egui::Slider::new(&mut self.clock.time_scale, MIN_TIME_SCALE..=MAX_TIME_SCALE)
+                    .logarithmic(true)
+                    .text("Speed ([ and ])")
+                    .ui(ui);
+                ui.add_enabled(
+                    !replaying,
+                    egui::Slider::new(&mut self.solver.params_mut().damping, 0.9..=1.0)
+                        .fixed_decimals(3)
+                        .text("Damping"),
+                );
+                egui::Slider::new(&mut self.clock.ticks_per_second, 10.0..=240.0)
+                    .text("Ticks per second")
+                    .ui(ui);
+                egui::Slider::new(&mut self.clock.max_substeps, 1..=16)
+                    .text("Max ticks per frame")
+                    .ui(ui);
+                egui::Checkbox::new(&mut self.interpolate, "Interpolate between ticks").ui(ui);
+                ui.horizontal(|ui| {
+                    for kind in SolverKind::ALL {
+                        ui.selectable_value(&mut solver_kind, kind, kind.name());
+                    }
+                });
+                ui.horizontal(|ui| {
+                    egui::DragValue::new(&mut self.pending_sim_size.x).range(simulation::MIN_SIZE..=simulation::MAX_SIZE).ui(ui);
+                    ui.label("x");
+                    egui::DragValue::new(&mut self.pending_sim_size.y).range(simulation::MIN_SIZE..=simulation::MAX_SIZE).ui(ui);
+                    if ui.button("Resize").clicked() {
+                        resize_to = Some(self.pending_sim_size);
+                    }
+                });
+                ui.collapsing("Boundaries", |ui| ui.add_enabled_ui(!replaying, |ui| {
+                    let boundaries = &mut self.solver.params_mut().boundaries;
+                    egui::Grid::new("boundaries").show(ui, |ui| {
+                        for edge in Edge::ALL {
+                            let mut mode = boundaries.get(edge);
+                            ui.label(edge.name());
+                            egui::ComboBox::from_id_salt(edge.name())
+                                .selected_text(mode.name())
+                                .show_ui(ui, |ui| {
+                                    for option in EdgeMode::ALL {
+                                        ui.selectable_value(&mut mode, option, option.name());
+                                    }
+                                });
+                            ui.end_row();
+                            if mode != boundaries.get(edge) {
+                                boundaries.set(edge, mode);
+                            }
+                        }
+                    });
+                    egui::Slider::new(&mut boundaries.sponge_thickness, 1..=64)
+                        .text("Sponge thickness")
+                        .ui(ui);
+                }));
+                ui.horizontal(|ui| {
+                    if ui.button("Reset field (R)").clicked() {
+                        inputs.push(InputEvent::Reset);
+                    }
+                    compare_with_reference = ui.button("Compare with reference").clicked();
+                    if let Some(difference) = self.reference_difference {
+                        ui.label(format!("max difference: {:.6}", difference));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Scene");
+                    egui::TextEdit::singleline(&mut self.scene_path).desired_width(160.0).ui(ui);
+                    egui::ComboBox::from_id_salt("scene_files")
+                        .selected_text("Pick")
+                        .show_ui(ui, |ui| {
+                            let directory = Path::new(&self.scene_path).parent()
+                                .filter(|parent| !parent.as_os_str().is_empty())
+                                .unwrap_or(Path::new("."));
+                            for path in scene::list(directory) {
+                                let name = path.display().to_string();
+                                ui.selectable_value(&mut self.scene_path, name.clone(), name);
+                            }
+                        });
+                    if ui.button("Save").clicked() {
+                        scene_action = Some(FileAction::Save);
+                    }
+                    if ui.button("Load").clicked() {
+                        scene_action = Some(FileAction::Load);
+                    }
+                });
+                if let Some(status) = &self.scene_status {
+                    ui.label(status);
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Snapshot");
+                    egui::TextEdit::singleline(&mut self.snapshot_path).desired_width(160.0).ui(ui);
+                    if ui.button("Save").clicked() {
+                        snapshot_action = Some(FileAction::Save);
+                    }
+                    if ui.button("Load").clicked() {
+                        snapshot_action = Some(FileAction::Load);
+                    }
+                });
+                if let Some(status) = &self.snapshot_status {
+                    ui.label(status);
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Recording");
+                    egui::TextEdit::singleline(&mut self.recording_path).desired_width(160.0).ui(ui);
+                    if self.recorder.is_some() {
+                        if ui.button("Stop and save").clicked() {
+                            recording_action = Some(RecordingAction::Stop);
+                        }
+                    } else if ui.button("Record").clicked() {
+                        recording_action = Some(RecordingAction::Start);
+                    }
+                    if self.replay.is_some() {
+                        if ui.button("Stop replay").clicked() {
+                            recording_action = Some(RecordingAction::StopReplay);
+                        }
+                    } else if ui.button("Replay").clicked() {
+                        recording_action = Some(RecordingAction::Replay);
+                    }
+                });
+                if let Some(status) = &self.recording_status {
+                    ui.label(status);
+                }
+
+                ui.separator();
+                ui.label("Brush");
+                ui.horizontal(|ui| {
+                    for tool in BrushTool::ALL {
+                        ui.selectable_value(&mut self.brush.tool, tool, tool.name());
+                    }
+                });
+                if self.brush.tool == BrushTool::Poke {
+                    poke_ui(ui, "click_poke", &mut self.poke);
+                } else {
+                    egui::Slider::new(&mut self.brush.radius, 1.0..=32.0)
+                        .text("Radius")
+                        .ui(ui);
+                    egui::Slider::new(&mut self.brush.falloff, 0.0..=1.0)
+                        .text("Falloff")
+                        .ui(ui);
+                    egui::Slider::new(&mut self.brush.medium, 0.0..=1.3)
+                        .text("Medium")
+                        .ui(ui);
+                }
+                if ui.button("Clear walls and medium").clicked() {
+                    inputs.push(InputEvent::ClearMedium);
+                }
+
+                ui.separator();
+                ui.label(format!("Emitters ({}/{})", self.emitters.len(), solver::MAX_SOURCES));
+                ui.collapsing("New emitter", |ui| {
+                    emitter_ui(ui, &mut self.new_emitter, false);
+                });
+                ui.add_enabled_ui(!replaying, |ui| {
+                    let mut remove = None;
+                    for (i, emitter) in self.emitters.iter_mut().enumerate() {
+                        ui.push_id(i, |ui| {
+                            ui.collapsing(format!("Emitter {}", i + 1), |ui| {
+                                emitter_ui(ui, emitter, true);
+                                if ui.button("Remove").clicked() {
+                                    remove = Some(i);
+                                }
+                            });
+                        });
+                    }
+                    if let Some(i) = remove {
+                        self.emitters.remove(i);
+                    }
+                    if ui.button("Remove all emitters").clicked() {
+                        self.emitters.clear();
+                    }
+                });
 
                 ui.separator();
                 ui.label("Render");
-                let mut config = self.render_config;
-                egui::Slider::new::<usize>(&mut config.grid_size, 2..=148)
+                egui::Slider::new::<usize>(&mut config.grid_size, RenderConfig::GRID_SIZES)
                     .integer()
                     .step_by(1.0)
                     .text("Grid Size")
                     .ui(ui);
-                egui::Slider::new(&mut config.step_size, 1.0..=5.0)
+                egui::Slider::new(&mut config.step_size, RenderConfig::STEP_SIZES)
                     .text("Step size")
                     .ui(ui);
-                egui::Slider::new(&mut config.prism_height, 1.0..=256.0)
+                egui::Slider::new(&mut config.prism_height, RenderConfig::PRISM_HEIGHTS)
                     .text("Prism Height")
                     .ui(ui);
                 ui.horizontal(|ui| {
-                    ui.selectable_value(&mut config.prism_type, PrismType::Square, "Square");
-                    ui.selectable_value(&mut config.prism_type, PrismType::Hex, "Hexagon");
+                    for prism in PrismType::ALL {
+                        ui.selectable_value(&mut config.prism_type, prism, prism.name());
+                    }
                 });
-
-                if self.render_config != config {
-                    self.render_config = config;
-                    match self.render_config.prism_type {
-                        PrismType::Square => {
-                            let mesh = mesh::square_prism(config.prism_height);
-                            let grid = mesh_grid::MeshGrid::square_grid(config.grid_size, config.step_size);
-                            renderer.sim.update_prism(&renderer.device, &mesh);
-                            renderer.sim.update_grid(&renderer.device, &grid);
-                        }
-                        PrismType::Hex => {
-                            let mesh = mesh::hex_prism(config.prism_height);
-                            let grid = mesh_grid::MeshGrid::hex_grid(config.grid_size, config.step_size);
-                            renderer.sim.update_prism(&renderer.device, &mesh);
-                            renderer.sim.update_grid(&renderer.device, &grid);
-                        }
+                ui.horizontal(|ui| {
+                    ui.label("Antialiasing");
+                    egui::ComboBox::from_id_salt("antialiasing")
+                        .selected_text(antialiasing.name())
+                        .show_ui(ui, |ui| {
+                            for mode in Antialiasing::ALL {
+                                ui.add_enabled_ui(supported_antialiasing.contains(&mode), |ui| {
+                                    ui.selectable_value(&mut antialiasing, mode, mode.name());
+                                });
+                            }
+                        });
+                });
+                egui::Grid::new("color_maps").show(ui, |ui| {
+                    for mode in RenderMode::ALL {
+                        let color_map = &mut color_maps[mode as usize];
+                        ui.label(format!("{} colours", mode.name()));
+                        egui::ComboBox::from_id_salt(("palette", mode.name()))
+                            .selected_text(&color_map.palette)
+                            .show_ui(ui, |ui| {
+                                for palette in &self.palettes {
+                                    ui.selectable_value(&mut color_map.palette, palette.name.clone(), &palette.name);
+                                }
+                            });
+                        egui::ComboBox::from_id_salt(("field", mode.name()))
+                            .selected_text(color_map.field.name())
+                            .show_ui(ui, |ui| {
+                                for field in ColorField::ALL {
+                                    ui.selectable_value(&mut color_map.field, field, field.name());
+                                }
+                            });
+                        egui::DragValue::new(&mut color_map.range)
+                            .range(0.001..=100.0)
+                            .speed(0.01)
+                            .prefix("Range ")
+                            .ui(ui);
+                        ui.end_row();
                     }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Palettes");
+                    egui::TextEdit::singleline(&mut self.palette_path).desired_width(160.0).ui(ui);
+                    if ui.button("Load").clicked() {
+                        load_palettes = true;
+                    }
+                });
+                if let Some(status) = &self.palette_status {
+                    ui.label(status);
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Screenshot");
+                    for scale in ScreenshotScale::ALL {
+                        ui.selectable_value(&mut self.screenshot.scale, scale, scale.name());
+                    }
+                    egui::Checkbox::new(&mut self.screenshot.downsample, "Downsample to window size").ui(ui);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Folder");
+                    egui::TextEdit::singleline(&mut self.screenshot.directory).desired_width(160.0).ui(ui);
+                    if ui.button("Take screenshot (F12)").clicked() {
+                        take_screenshot = true;
+                    }
+                });
+                if let Some(status) = &self.screenshot_status {
+                    ui.label(status);
                 }
 
                 ui.separator();
@@ -299,26 +1444,240 @@ impl App<'_> {
                     .text("Target ratio")
                     .ui(ui);
 
+                ui.separator();
+                ui.label("Lighting");
+                ui.horizontal(|ui| {
+                    ui.label("Ambient");
+                    ui.color_edit_button_rgb(&mut self.lighting.ambient);
+                });
+                egui::Slider::new(&mut self.lighting.specular, 0.0..=2.0)
+                    .text("Specular")
+                    .ui(ui);
+                egui::Slider::new(&mut self.lighting.shininess, 1.0..=256.0)
+                    .logarithmic(true)
+                    .text("Shininess")
+                    .ui(ui);
+                egui::Checkbox::new(&mut self.lighting.shadows, "Shadows")
+                    .ui(ui)
+                    .on_hover_text("Cast by the first enabled directional light");
+                let mut remove = None;
+                for (i, light) in self.lighting.lights.iter_mut().enumerate() {
+                    ui.push_id(("light", i), |ui| {
+                        ui.collapsing(format!("Light {} ({})", i + 1, light.source.name()), |ui| {
+                            light_ui(ui, light);
+                            if ui.button("Remove").clicked() {
+                                remove = Some(i);
+                            }
+                        });
+                    });
+                }
+                if let Some(i) = remove {
+                    self.lighting.lights.remove(i);
+                }
+                let can_add = self.lighting.lights.len() < lighting::MAX_LIGHTS;
+                if ui.add_enabled(can_add, egui::Button::new("Add light")).clicked() {
+                    self.lighting.lights.push(Light::default());
+                }
+
+                ui.separator();
+                ui.label("Export");
+                ui.horizontal(|ui| {
+                    egui::DragValue::new(&mut self.export_settings.frames).range(1..=100_000).prefix("Frames ").ui(ui);
+                    egui::DragValue::new(&mut self.export_settings.ticks_per_frame).range(0..=64).prefix("Ticks per frame ").ui(ui);
+                    egui::DragValue::new(&mut self.export_settings.fps).range(1..=120).prefix("FPS ").ui(ui);
+                });
+                ui.horizontal(|ui| {
+                    egui::DragValue::new(&mut self.export_settings.size.x).range(16..=8192).ui(ui);
+                    ui.label("x");
+                    egui::DragValue::new(&mut self.export_settings.size.y).range(16..=8192).ui(ui);
+                    egui::Checkbox::new(&mut self.export_settings.orbit, "Orbit once, so the clip loops").ui(ui);
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Folder");
+                    egui::TextEdit::singleline(&mut self.export_path).desired_width(160.0).ui(ui);
+                    egui::ComboBox::from_id_salt("export_video")
+                        .selected_text(self.export_video.map_or("PNGs only", |format| format.name()))
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.export_video, None, "PNGs only");
+                            for format in VideoFormat::ALL {
+                                ui.selectable_value(&mut self.export_video, Some(format), format.name());
+                            }
+                        });
+                });
+                match &self.export {
+                    Some(export) => {
+                        ui.horizontal(|ui| {
+                            ui.label(format!("Frame {}/{}", export.written(), export.settings.frames));
+                            if ui.button("Cancel").clicked() {
+                                cancel_export = true;
+                            }
+                        });
+                    }
+                    None => {
+                        if ui.button("Export").clicked() {
+                            start_export = true;
+                        }
+                    }
+                }
+                if let Some(status) = &self.export_status {
+                    ui.label(status);
+                }
+
                 ui.separator();
                 ui.label("Raindrops");
-                egui::Checkbox::new(&mut self.raindrop_config.enabled, "Enabled").ui(ui);
-                egui::Slider::new(&mut self.raindrop_config.delay, 0..=1000).integer()
+                egui::Checkbox::new(&mut self.raindrops.settings.enabled, "Enabled").ui(ui);
+                egui::Slider::new(&mut self.raindrops.settings.delay, 0..=1000).integer()
                     .text("Delay")
                     .ui(ui);
+                poke_ui(ui, "raindrop_poke", &mut self.raindrops.settings.poke);
+                ui.horizontal(|ui| {
+                    egui::DragValue::new(&mut self.seed).prefix("Seed ").ui(ui);
+                    if ui.button("Restart rain").clicked() {
+                        self.raindrops.reseed(self.seed);
+                    }
+                });
+            });
+
+        if !self.shader_errors.is_empty() {
+            let context = self.renderer.as_mut().unwrap().egui_renderer().context().clone();
+            egui::Window::new("Shader error")
+                .resizable(true)
+                .vscroll(true)
+                .show(&context, |ui| {
+                    ui.label("Still drawing with the last version that compiled");
+                    for (mode, error) in &self.shader_errors {
+                        ui.separator();
+                        ui.strong(mode.shader_file());
+                        ui.monospace(error);
+                    }
+                });
+        }
+
+        for event in inputs {
+            self.apply_input(event);
+        }
+        if solver_kind != self.solver.kind() {
+            self.apply_input(InputEvent::SetSolver(solver_kind));
+        }
+        if self.render_config != config {
+            self.render_config = config;
+            self.rebuild_prism();
+        }
+        let renderer = self.renderer.as_mut().unwrap();
+        if renderer.antialiasing() != antialiasing {
+            renderer.set_antialiasing(antialiasing);
+        }
+        if self.color_maps != color_maps {
+            self.color_maps = color_maps;
+            self.update_color_maps();
+        }
+        if load_palettes {
+            let path = PathBuf::from(&self.palette_path);
+            self.palette_status = Some(match self.load_palettes(&path) {
+                Ok(count) => format!("Loaded {} palettes from {}", count, path.display()),
+                Err(error) => format!("Error: {}", error),
+            });
+        }
+        if let Some(size) = resize_to {
+            self.apply_input(InputEvent::Resize(size));
+        }
+        if compare_with_reference {
+            self.compare_with_reference();
+        }
+        if start_export {
+            self.start_export();
+        }
+        if take_screenshot {
+            self.take_screenshot();
+        }
+        if restore_defaults {
+            self.apply_config(&Config::default());
+        }
+        if let Some(preset) = picked_preset {
+            self.preset_name = preset.name.clone();
+            self.pick_preset(preset);
+        }
+        if let Some(action) = preset_action {
+            let result = match action {
+                PresetAction::Save => self.save_preset(),
+                PresetAction::Delete => {
+                    self.user_presets.retain(|preset| preset.name != self.preset_name);
+                    preset::save_user(&self.user_presets)
+                }
+            };
+            self.preset_status = Some(match result {
+                Ok(path) => format!("Saved presets to {}", path.display()),
+                Err(error) => format!("Error: {}", error),
+            });
+        }
+        if let Some(export) = self.export.take_if(|_| cancel_export) {
+            self.export_status = Some(format!("Cancelled after {} frames", export.written()));
+        }
+        if let Some(action) = recording_action {
+            let path = PathBuf::from(&self.recording_path);
+            let result = match action {
+                RecordingAction::Start => {
+                    self.start_recording();
+                    Ok(())
+                }
+                RecordingAction::Stop => self.stop_recording(&path)
+                    .map(|()| self.recording_status = Some(format!("Saved {}", path.display()))),
+                RecordingAction::Replay => self.start_replay(&path),
+                RecordingAction::StopReplay => {
+                    self.replay = None;
+                    self.recording_status = None;
+                    Ok(())
+                }
+            };
+            if let Err(error) = result {
+                self.recording_status = Some(format!("Error: {}", error));
+            }
+        }
+        if let Some(action) = scene_action {
+            let path = PathBuf::from(&self.scene_path);
+            let result = match action {
+                FileAction::Save => self.save_scene(&path),
+                FileAction::Load => self.load_scene(&path),
+            };
+            self.scene_status = Some(match result {
+                Ok(()) => format!("{} {}", action.past_tense(), path.display()),
+                Err(error) => format!("Error: {}", error),
+            });
+        }
+        if let Some(action) = snapshot_action {
+            let path = PathBuf::from(&self.snapshot_path);
+            let result = match action {
+                FileAction::Save => self.save_snapshot(&path),
+                FileAction::Load => self.load_snapshot(&path),
+            };
+            self.snapshot_status = Some(match result {
+                Ok(()) => format!("{} {}", action.past_tense(), path.display()),
+                Err(error) => format!("Error: {}", error),
             });
+        }
+    }
+
+    fn compare_with_reference(&mut self) {
+        const COMPARE_TICKS: u32 = 100;
+        let renderer = self.renderer.as_ref().unwrap();
+        let difference = solver::compare_with_reference(
+            self.solver.as_ref(), COMPARE_TICKS, &renderer.device, &renderer.queue,
+        );
+        info!("max difference from reference after {} ticks: {}", COMPARE_TICKS, difference);
+        self.reference_difference = Some(difference);
     }
 
     fn render(&mut self) -> bool {
         let renderer = self.renderer.as_mut().unwrap();
-        renderer.egui_renderer.begin_frame(self.window.as_ref().unwrap());
+        renderer.egui_renderer().begin_frame(self.window.as_ref().unwrap());
         self.render_ui();
 
         use wgpu::SurfaceError as SE;
         let renderer = self.renderer.as_mut().unwrap();
-        let camera_transform = renderer.projection.calc_matrix() * self.camera.calc_matrix();
-        renderer.sim.set_camera_transform(&renderer.queue, camera_transform);
-        let (divisions, sim_data) = self.simulation.current_state();
-        renderer.sim.update_sim_data(&renderer.queue, divisions, sim_data);
+        renderer.set_camera(&self.camera);
+        renderer.set_lighting(&self.lighting, self.grid_extent, self.render_config.prism_height);
+        let alpha = if self.interpolate { self.clock.alpha() } else { 1.0 };
+        renderer.update_field(self.solver.output(), alpha);
         match renderer.render() {
             Ok(_) => {}
             Err(SE::Lost | SE::Outdated) => renderer.resize(renderer.size),
@@ -334,6 +1693,100 @@ impl App<'_> {
     }
 }
 
+fn poke_ui(ui: &mut egui::Ui, id: &str, poke: &mut PokeSettings) {
+    ui.horizontal(|ui| {
+        for shape in PokeShape::ALL {
+            ui.selectable_value(&mut poke.shape, shape, shape.name());
+        }
+    });
+    ui.horizontal(|ui| {
+        egui::Slider::new(&mut poke.radius, PokeSettings::RADII)
+            .text("Poke radius")
+            .ui(ui);
+        egui::ComboBox::from_id_salt(id)
+            .selected_text(poke.unit.name())
+            .show_ui(ui, |ui| {
+                for unit in RadiusUnit::ALL {
+                    ui.selectable_value(&mut poke.unit, unit, unit.name());
+                }
+            });
+    });
+    egui::Slider::new(&mut poke.amplitude, PokeSettings::AMPLITUDES)
+        .text("Poke amplitude")
+        .ui(ui);
+}
+
+/// Settings for a single light
+fn light_ui(ui: &mut egui::Ui, light: &mut Light) {
+    ui.horizontal(|ui| {
+        egui::Checkbox::new(&mut light.enabled, "Enabled").ui(ui);
+        let mut directional = matches!(light.source, LightSource::Directional { .. });
+        ui.selectable_value(&mut directional, true, "Directional");
+        ui.selectable_value(&mut directional, false, "Point");
+        light.source = match (light.source, directional) {
+            (LightSource::Point { position }, true) => LightSource::Directional { direction: -position },
+            (LightSource::Directional { direction }, false) => LightSource::Point { position: -direction },
+            (source, _) => source,
+        };
+    });
+    let (label, vector, range) = match &mut light.source {
+        LightSource::Directional { direction } => ("Direction", direction, -1.0..=1.0),
+        LightSource::Point { position } => ("Position", position, -3.0..=3.0),
+    };
+    ui.horizontal(|ui| {
+        ui.label(label);
+        for value in [&mut vector.x, &mut vector.y, &mut vector.z] {
+            egui::DragValue::new(value).range(range.clone()).speed(0.01).ui(ui);
+        }
+    });
+    ui.horizontal(|ui| {
+        ui.color_edit_button_rgb(&mut light.color);
+        egui::Slider::new(&mut light.intensity, 0.0..=4.0)
+            .text("Intensity")
+            .ui(ui);
+    });
+}
+
+/// Settings for a single emitter. Positions only make sense once it has been placed.
+fn emitter_ui(ui: &mut egui::Ui, emitter: &mut Emitter, placed: bool) {
+    ui.horizontal(|ui| {
+        egui::Checkbox::new(&mut emitter.enabled, "Enabled").ui(ui);
+        for shape in EmitterShape::ALL {
+            ui.selectable_value(&mut emitter.shape, shape, shape.name());
+        }
+    });
+    if placed {
+        ui.horizontal(|ui| {
+            ui.label("Start");
+            egui::DragValue::new(&mut emitter.start.x).range(0.0..=1.0).speed(0.005).ui(ui);
+            egui::DragValue::new(&mut emitter.start.y).range(0.0..=1.0).speed(0.005).ui(ui);
+        });
+        if emitter.shape == EmitterShape::Line {
+            ui.horizontal(|ui| {
+                ui.label("End");
+                egui::DragValue::new(&mut emitter.end.x).range(0.0..=1.0).speed(0.005).ui(ui);
+                egui::DragValue::new(&mut emitter.end.y).range(0.0..=1.0).speed(0.005).ui(ui);
+            });
+        }
+    }
+    egui::Slider::new(&mut emitter.frequency, 0.005..=0.25)
+        .logarithmic(true)
+        .text("Frequency (cycles per tick)")
+        .ui(ui);
+    egui::Slider::new(&mut emitter.amplitude, 0.0..=4.0)
+        .text("Amplitude")
+        .ui(ui);
+    egui::Slider::new(&mut emitter.phase, 0.0..=std::f32::consts::TAU)
+        .text("Phase")
+        .ui(ui);
+    egui::Slider::new(&mut emitter.duty_cycle, 0.05..=1.0)
+        .text("Duty cycle")
+        .ui(ui);
+    egui::Slider::new(&mut emitter.radius, 0.5..=8.0)
+        .text("Radius")
+        .ui(ui);
+}
+
 impl ApplicationHandler for App<'_> {
     fn resumed(&mut self, event_loop: &ActiveEventLoop) {
         if self.window.is_none() {
@@ -348,6 +1801,20 @@ impl ApplicationHandler for App<'_> {
         match event {
             WindowEvent::CloseRequested => {
                 info!("Window close button pressed: stopping");
+                match self.config().save() {
+                    Ok(path) => info!("Saved settings to {}", path.display()),
+                    Err(error) => log::error!("Couldn't save settings: {}", error),
+                }
+                if let Some(path) = &self.options.save_on_exit {
+                    if let Err(error) = self.save_snapshot(path) {
+                        log::error!("Couldn't save snapshot {}: {}", path.display(), error);
+                    }
+                }
+                if let Some(path) = self.options.record.clone() {
+                    if let Err(error) = self.stop_recording(&path) {
+                        log::error!("Couldn't save recording {}: {}", path.display(), error);
+                    }
+                }
                 event_loop.exit();
             }
             WindowEvent::RedrawRequested => self.handle_next_frame(event_loop),
@@ -362,8 +1829,35 @@ impl ApplicationHandler for App<'_> {
 
 fn main() {
     env_logger::init();
+    let subcommand = std::env::args().nth(1);
+    if let Some(usage) = match subcommand.as_deref() {
+        Some("batch") => Some(batch::USAGE),
+        Some("export") => Some(batch::EXPORT_USAGE),
+        _ => None,
+    } {
+        let args = std::env::args().skip(2);
+        let options = match subcommand.as_deref() {
+            Some("export") => batch::BatchOptions::from_export_args(args),
+            _ => batch::BatchOptions::from_args(args),
+        };
+        let result = options
+            .map_err(|error| format!("{}\n{}", error, usage))
+            .and_then(batch::run);
+        if let Err(error) = result {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+        return;
+    }
+    let options = match Options::from_args() {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n{}", error, USAGE);
+            std::process::exit(2);
+        }
+    };
     let event_loop = EventLoop::new().unwrap();
     event_loop.set_control_flow(ControlFlow::Poll);
-    let mut app = App::new();
+    let mut app = App::new(options);
     event_loop.run_app(&mut app).unwrap();
 }
@@ -1,7 +1,6 @@
 use bytemuck::{Pod, Zeroable};
-use macaw::{UVec2, uvec2, Vec2, vec2};
+use macaw::{UVec2, Vec2, vec2};
 use wgpu::util::DeviceExt;
-use crate::simulation;
 
 #[repr(C)]
 #[derive(Pod, Zeroable, Copy, Clone)]
@@ -27,53 +26,57 @@ impl Instance {
 
 pub struct MeshGrid {
     pub instances: Vec<Instance>,
+    /// World space size of the area the simulation is stretched over, centered on the origin
+    pub extent: Vec2,
 }
 
 impl MeshGrid {
-    pub fn square_grid(size: usize, step_size: f32) -> Self {
-        let length = size as f32 * step_size;
-        let half_length = length / 2.0;
-        let uv_step = 1.0 / (size as f32);
+    pub fn square_grid(size: usize, step_size: f32, sim_size: UVec2) -> Self {
+        let counts = (fit_extent(size as f32, sim_size).round().as_uvec2()).max(UVec2::ONE);
+        let extent = counts.as_vec2() * step_size;
+        let half_extent = extent / 2.0;
         let mut instances = Vec::new();
-        for y in 0..size {
-            for x in 0..size {
+        for y in 0..counts.y {
+            for x in 0..counts.x {
+                let position = vec2(x as f32, y as f32) * step_size - half_extent;
                 instances.push(Instance {
-                    position: vec2(x as f32, y as f32) * step_size - half_length,
-                    uv: uvec2(
-                        (x as f32 * uv_step * simulation::DIVISIONS as f32) as u32,
-                        (y as f32 * uv_step * simulation::DIVISIONS as f32) as u32,
-                    ),
+                    position,
+                    uv: sim_coord(position, extent, sim_size),
                 })
             }
         }
         Self {
-            instances
+            instances,
+            extent,
         }
     }
 
-    pub fn hex_grid(size: usize, step_size: f32) -> Self {
+    pub fn hex_grid(size: usize, step_size: f32, sim_size: UVec2) -> Self {
         // Following algo from: https://www.redblobgames.com/grids/hexagons/#range
         let mut instances = Vec::new();
         let size = size as i32;
 
         let grid_width = 2.0 * size as f32 * 3.0_f32.sqrt() * 0.5;
-        let uv_step = 1.0 / grid_width;
+        let extent = fit_extent(grid_width * step_size, sim_size);
+        let half_extent = extent / 2.0;
 
         for q in -size..=size {
             for r in (-size).max(-q - size)..=size.min(-q + size) {
-                let coord_2d = hex_coord_2d(q, r);
+                let position = hex_coord_2d(q, r) * step_size;
+                // Trim the hexagon down to the sim's aspect ratio
+                if (position.abs() - half_extent).max_element() > step_size * 0.25 {
+                    continue;
+                }
                 instances.push(Instance {
-                    position: coord_2d * step_size,
-                    uv: uvec2(
-                        ((coord_2d.x + (grid_width * 0.5)) * uv_step * simulation::DIVISIONS as f32) as u32,
-                        ((coord_2d.y + (grid_width * 0.5)) * uv_step * simulation::DIVISIONS as f32) as u32,
-                    ),
+                    position,
+                    uv: sim_coord(position, extent, sim_size),
                 })
             }
         }
 
         Self {
-            instances
+            instances,
+            extent,
         }
     }
 
@@ -92,6 +95,17 @@ impl MeshGrid {
     }
 }
 
+/// Shrinks a square of side `length` along the sim's shorter side so cells stay square
+fn fit_extent(length: f32, sim_size: UVec2) -> Vec2 {
+    let sim = sim_size.as_vec2();
+    length * sim / sim.max_element()
+}
+
+fn sim_coord(position: Vec2, extent: Vec2, sim_size: UVec2) -> UVec2 {
+    let normalized = (position + extent / 2.0) / extent;
+    (normalized * sim_size.as_vec2()).as_uvec2().min(sim_size - 1)
+}
+
 fn hex_coord_2d(q: i32, r: i32) -> Vec2 {
     return vec2(
         3.0_f32.sqrt() * q as f32 + 3.0_f32.sqrt() * 0.5 * r as f32,
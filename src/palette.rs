@@ -0,0 +1,137 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::sim_renderer::RenderMode;
+
+/// Entries in the lookup texture the shaders sample, see `Palette::lut`
+pub const LUT_SIZE: u32 = 256;
+
+/// A colour gradient the field is drawn with
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Palette {
+    pub name: String,
+    /// sRGB colours spread evenly from the low end to the high end, at least one
+    pub colors: Vec<[u8; 3]>,
+}
+
+impl Palette {
+    fn from_hex(name: &str, colors: &[u32]) -> Self {
+        Self {
+            name: name.into(),
+            colors: colors.iter()
+                .map(|color| [(color >> 16) as u8, (color >> 8) as u8, *color as u8])
+                .collect(),
+        }
+    }
+
+    /// `LUT_SIZE` RGBA texels blending between the colours
+    pub fn lut(&self) -> Vec<u8> {
+        let last = self.colors.len().saturating_sub(1);
+        let mut texels = Vec::with_capacity(LUT_SIZE as usize * 4);
+        for i in 0..LUT_SIZE {
+            let position = i as f32 / (LUT_SIZE - 1) as f32 * last as f32;
+            let index = (position as usize).min(last.saturating_sub(1));
+            let t = position - index as f32;
+            let from = self.colors.get(index).copied().unwrap_or_default();
+            let to = self.colors.get(index + 1).copied().unwrap_or(from);
+            for channel in 0..3 {
+                let value = from[channel] as f32 + (to[channel] as f32 - from[channel] as f32) * t;
+                texels.push(value.round() as u8);
+            }
+            texels.push(255);
+        }
+        texels
+    }
+}
+
+/// Perceptual maps for unsigned fields and a diverging one for signed height
+pub fn built_in() -> Vec<Palette> {
+    vec![
+        Palette::from_hex("Viridis", &[
+            0x440154, 0x472d7b, 0x3b528b, 0x2c728e, 0x21918c, 0x28ae80, 0x5ec962, 0xaddc30, 0xfde725,
+        ]),
+        Palette::from_hex("Magma", &[
+            0x000004, 0x180f3d, 0x440f76, 0x721f81, 0x9e2f7f, 0xcd4071, 0xf1605d, 0xfd9668, 0xfeca8d,
+            0xfcfdbf,
+        ]),
+        Palette::from_hex("Blue-red", &[
+            0x053061, 0x2166ac, 0x4393c3, 0x92c5de, 0xf7f7f7, 0xfddbc7, 0xf4a582, 0xd6604d, 0xb2182b,
+            0x67001f,
+        ]),
+        Palette::from_hex("Greyscale", &[0x000000, 0xffffff]),
+    ]
+}
+
+/// Custom palettes from a RON file holding a list of them
+pub fn load(path: &Path) -> io::Result<Vec<Palette>> {
+    let palettes: Vec<Palette> = ron::from_str(&fs::read_to_string(path)?)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+    if let Some(palette) = palettes.iter().find(|palette| palette.colors.is_empty()) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("{} has no colours", palette.name)));
+    }
+    Ok(palettes)
+}
+
+/// Which part of the field the colours show
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum ColorField {
+    /// Signed, the middle of the palette is calm water
+    Height,
+    /// Signed like height
+    Velocity,
+    /// How fast waves move through each cell, walls stay dark whatever the field
+    Medium,
+    /// Height and velocity squared, bright wherever the water is moving
+    Energy,
+}
+
+impl ColorField {
+    pub const ALL: [ColorField; 4] = [ColorField::Height, ColorField::Velocity, ColorField::Medium, ColorField::Energy];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColorField::Height => "Height",
+            ColorField::Velocity => "Velocity",
+            ColorField::Medium => "Medium",
+            ColorField::Energy => "Energy",
+        }
+    }
+}
+
+/// How one render mode colours the field
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorMap {
+    /// Name of a built in or loaded palette, the first built in one if it isn't around
+    pub palette: String,
+    pub field: ColorField,
+    /// Field value at the top of the palette. Signed fields put minus this at the bottom.
+    pub range: f32,
+}
+
+impl Default for ColorMap {
+    fn default() -> Self {
+        Self {
+            palette: "Viridis".into(),
+            field: ColorField::Height,
+            range: 1.0,
+        }
+    }
+}
+
+impl ColorMap {
+    /// What each render mode starts out with: signed height on the flat view, where the diverging
+    /// map reads well, and viridis on the prisms
+    pub fn default_for(mode: RenderMode) -> Self {
+        match mode {
+            RenderMode::Texture => Self { palette: "Blue-red".into(), ..Self::default() },
+            RenderMode::Prism => Self::default(),
+        }
+    }
+
+    /// The palette to draw with out of `palettes`
+    pub fn find_palette<'a>(&self, palettes: &'a [Palette]) -> Option<&'a Palette> {
+        palettes.iter().find(|palette| palette.name == self.palette)
+    }
+}
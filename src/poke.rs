@@ -0,0 +1,188 @@
+use std::f32::consts::PI;
+use std::ops::RangeInclusive;
+use macaw::{UVec2, Vec2, vec2};
+use serde::{Deserialize, Serialize};
+use crate::simulation::WavePoint;
+
+/// How a poke's strength falls off from its center. The discriminants are shared with
+/// simulation.wgsl.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum PokeShape {
+    /// Full strength everywhere inside the radius, like the original 5x5 poke
+    Box = 0,
+    Gaussian = 1,
+    /// Half a cosine wave from the center to the radius
+    Cosine = 2,
+    /// A cosine bump that peaks at 70% of the radius, leaving the center flat
+    Ring = 3,
+}
+
+impl PokeShape {
+    pub const ALL: [PokeShape; 4] = [PokeShape::Box, PokeShape::Gaussian, PokeShape::Cosine, PokeShape::Ring];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PokeShape::Box => "Box",
+            PokeShape::Gaussian => "Gaussian",
+            PokeShape::Cosine => "Cosine",
+            PokeShape::Ring => "Ring",
+        }
+    }
+
+    /// Strength at `distance` from the center, as a fraction of the radius
+    pub fn weight(&self, distance: f32) -> f32 {
+        if distance > 1.0 {
+            return 0.0;
+        }
+        match self {
+            PokeShape::Box => 1.0,
+            // Cut off at three standard deviations
+            PokeShape::Gaussian => (-4.5 * distance * distance).exp(),
+            PokeShape::Cosine => 0.5 + 0.5 * (PI * distance).cos(),
+            PokeShape::Ring => {
+                let offset = (distance - 0.7) / 0.3;
+                if offset.abs() >= 1.0 { 0.0 } else { 0.5 + 0.5 * (PI * offset).cos() }
+            }
+        }
+    }
+}
+
+/// A one-off disturbance of the water, sized in cells
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Poke {
+    pub shape: PokeShape,
+    pub radius: f32,
+    /// Height added at full strength, negative for a trough
+    pub amplitude: f32,
+}
+
+impl Poke {
+    /// Height added to the cell with its center at `point`
+    pub fn value_at(&self, center: Vec2, point: Vec2) -> f32 {
+        self.amplitude * self.shape.weight(point.distance(center) / self.radius.max(f32::EPSILON))
+    }
+
+    /// Adds the poke to `points` around `center`, given in cells
+    pub fn apply(&self, points: &mut [WavePoint], size: UVec2, center: Vec2) {
+        let width = size.x as usize;
+        let max = size.as_vec2();
+        let min_corner = (center - self.radius).floor().clamp(Vec2::ZERO, max);
+        let max_corner = (center + self.radius).ceil().clamp(Vec2::ZERO, max);
+        for y in min_corner.y as usize..max_corner.y as usize {
+            for x in min_corner.x as usize..max_corner.x as usize {
+                points[y * width + x].value += self.value_at(center, vec2(x as f32 + 0.5, y as f32 + 0.5));
+            }
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum RadiusUnit {
+    Cells,
+    /// Size in the 3D view, so a poke covers the same number of prisms whatever the grid size
+    World,
+}
+
+impl RadiusUnit {
+    pub const ALL: [RadiusUnit; 2] = [RadiusUnit::Cells, RadiusUnit::World];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RadiusUnit::Cells => "Cells",
+            RadiusUnit::World => "World units",
+        }
+    }
+}
+
+/// Poke as picked in the settings window, before it is sized for the current grid
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PokeSettings {
+    pub shape: PokeShape,
+    pub radius: f32,
+    pub unit: RadiusUnit,
+    pub amplitude: f32,
+}
+
+impl Default for PokeSettings {
+    fn default() -> Self {
+        Self {
+            shape: PokeShape::Gaussian,
+            radius: 3.0,
+            unit: RadiusUnit::Cells,
+            amplitude: 1.0,
+        }
+    }
+}
+
+impl PokeSettings {
+    /// What the settings window goes between
+    pub const RADII: RangeInclusive<f32> = 0.5..=32.0;
+    pub const AMPLITUDES: RangeInclusive<f32> = -4.0..=4.0;
+
+    pub fn poke(&self, cells_per_world_unit: f32) -> Poke {
+        let radius = match self.unit {
+            RadiusUnit::Cells => self.radius,
+            RadiusUnit::World => self.radius * cells_per_world_unit,
+        };
+        Poke {
+            shape: self.shape,
+            radius,
+            amplitude: self.amplitude,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shapes_fade_out_by_the_radius() {
+        for shape in PokeShape::ALL {
+            assert_eq!(shape.weight(1.01), 0.0, "{}", shape.name());
+        }
+        assert_eq!(PokeShape::Box.weight(1.0), 1.0);
+        for shape in [PokeShape::Gaussian, PokeShape::Cosine, PokeShape::Ring] {
+            assert!(shape.weight(1.0) < 0.02, "{}", shape.name());
+        }
+    }
+
+    #[test]
+    fn shapes_peak_where_expected() {
+        assert_eq!(PokeShape::Box.weight(0.0), 1.0);
+        assert_eq!(PokeShape::Gaussian.weight(0.0), 1.0);
+        assert_eq!(PokeShape::Cosine.weight(0.0), 1.0);
+        assert!((PokeShape::Cosine.weight(0.5) - 0.5).abs() < 1e-6);
+        assert_eq!(PokeShape::Ring.weight(0.0), 0.0);
+        assert_eq!(PokeShape::Ring.weight(0.7), 1.0);
+    }
+
+    #[test]
+    fn world_radius_is_scaled_to_cells() {
+        let settings = PokeSettings {
+            radius: 2.0,
+            unit: RadiusUnit::World,
+            ..PokeSettings::default()
+        };
+        assert_eq!(settings.poke(8.0).radius, 16.0);
+        let settings = PokeSettings { unit: RadiusUnit::Cells, ..settings };
+        assert_eq!(settings.poke(8.0).radius, 2.0);
+    }
+
+    #[test]
+    fn apply_stays_inside_the_grid() {
+        let poke = Poke { shape: PokeShape::Box, radius: 2.0, amplitude: 1.0 };
+        let size = UVec2::new(4, 4);
+        let mut points = vec![WavePoint::default(); 16];
+        poke.apply(&mut points, size, vec2(0.0, 0.0));
+        let values: Vec<f32> = points.iter().map(|point| point.value).collect();
+        // Cell centers within 2 cells of the corner
+        assert_eq!(values, [
+            1.0, 1.0, 0.0, 0.0,
+            1.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 0.0,
+            0.0, 0.0, 0.0, 0.0,
+        ]);
+    }
+}
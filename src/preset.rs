@@ -0,0 +1,167 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use crate::camera::CameraConfig;
+use crate::config;
+use crate::poke::PokeSettings;
+use crate::raindrops::RaindropSettings;
+use crate::sim_renderer::{PrismType, RenderConfig, RenderMode};
+use crate::solver::SimParams;
+
+/// A look and behaviour picked from the settings window. Unlike a scene it leaves the water,
+/// emitters and grid size alone.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Preset {
+    pub name: String,
+    pub params: SimParams,
+    pub raindrops: RaindropSettings,
+    pub render_mode: RenderMode,
+    pub render: RenderConfig,
+    pub camera: CameraConfig,
+}
+
+impl Default for Preset {
+    fn default() -> Self {
+        Self {
+            name: "Preset".into(),
+            params: SimParams::default(),
+            raindrops: RaindropSettings::default(),
+            render_mode: RenderMode::Prism,
+            render: RenderConfig::default(),
+            camera: CameraConfig::default(),
+        }
+    }
+}
+
+impl Preset {
+    /// `t` of the way from `self` to `other`. The simulation and camera numbers are blended,
+    /// everything else switches to `other` straight away. The render settings are left to the
+    /// end of a transition, rebuilding the prisms every frame would be too slow.
+    pub fn blend(&self, other: &Preset, t: f32) -> Preset {
+        let lerp = |from: f32, to: f32| from + (to - from) * t;
+        let mut preset = other.clone();
+        preset.params.damping = lerp(self.params.damping, other.params.damping);
+        preset.raindrops.delay = lerp(self.raindrops.delay as f32, other.raindrops.delay as f32).round() as u32;
+        preset.raindrops.poke.radius = lerp(self.raindrops.poke.radius, other.raindrops.poke.radius);
+        preset.raindrops.poke.amplitude = lerp(self.raindrops.poke.amplitude, other.raindrops.poke.amplitude);
+        preset.camera.rotation_speed = lerp(self.camera.rotation_speed, other.camera.rotation_speed);
+        preset.camera.distance = lerp(self.camera.distance, other.camera.distance);
+        preset.camera.angle = lerp(self.camera.angle, other.camera.angle);
+        preset.camera.target_ratio = lerp(self.camera.target_ratio, other.camera.target_ratio);
+        preset
+    }
+}
+
+/// The presets that ship with the app
+pub fn built_in() -> Vec<Preset> {
+    vec![
+        Preset {
+            name: "Calm pond".into(),
+            params: SimParams { damping: 0.995, ..SimParams::default() },
+            raindrops: RaindropSettings {
+                enabled: true,
+                delay: 400,
+                poke: PokeSettings { radius: 2.0, amplitude: 0.5, ..PokeSettings::default() },
+            },
+            render: RenderConfig { grid_size: 48, prism_height: 3.0, ..RenderConfig::default() },
+            camera: CameraConfig { rotation_speed: 0.05, angle: 30.0, ..CameraConfig::default() },
+            ..Preset::default()
+        },
+        Preset {
+            name: "Storm".into(),
+            params: SimParams { damping: 0.97, ..SimParams::default() },
+            raindrops: RaindropSettings {
+                enabled: true,
+                delay: 6,
+                poke: PokeSettings { radius: 4.0, amplitude: 2.0, ..PokeSettings::default() },
+            },
+            render: RenderConfig { grid_size: 64, prism_height: 12.0, ..RenderConfig::default() },
+            camera: CameraConfig {
+                rotation_speed: 0.4,
+                change_angle: true,
+                angle: 25.0,
+                ..CameraConfig::default()
+            },
+            ..Preset::default()
+        },
+        Preset {
+            name: "Tall hex towers".into(),
+            render: RenderConfig {
+                prism_type: PrismType::Hex,
+                prism_height: 96.0,
+                grid_size: 16,
+                step_size: 1.5,
+            },
+            camera: CameraConfig { distance: 1.6, angle: 35.0, ..CameraConfig::default() },
+            ..Preset::default()
+        },
+        Preset {
+            name: "Top-down flat".into(),
+            render_mode: RenderMode::Texture,
+            camera: CameraConfig { rotation_enabled: false, ..CameraConfig::default() },
+            ..Preset::default()
+        },
+    ]
+}
+
+/// `presets.ron` next to the saved settings
+pub fn path() -> Option<PathBuf> {
+    Some(config::directory()?.join("presets.ron"))
+}
+
+/// Presets the user saved, none if nothing has been saved yet
+pub fn load_user() -> io::Result<Vec<Preset>> {
+    let Some(path) = path() else {
+        return Ok(Vec::new());
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error),
+    };
+    ron::from_str(&text).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+}
+
+pub fn save_user(presets: &[Preset]) -> io::Result<PathBuf> {
+    let path = path().ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    let text = ron::ser::to_string_pretty(presets, ron::ser::PrettyConfig::default())
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+    fs::write(&path, text)?;
+    Ok(path)
+}
+
+/// Moves the settings from one preset to another over a few seconds
+pub struct Transition {
+    from: Preset,
+    to: Preset,
+    elapsed: f32,
+    duration: f32,
+}
+
+impl Transition {
+    pub fn new(from: Preset, to: Preset, duration: f32) -> Self {
+        Self {
+            from,
+            to,
+            elapsed: 0.0,
+            duration,
+        }
+    }
+
+    /// Moves on by `delta` seconds and returns the settings to use now
+    pub fn advance(&mut self, delta: f32) -> Preset {
+        self.elapsed += delta;
+        let t = (self.elapsed / self.duration).clamp(0.0, 1.0);
+        // Smoothstep, so the camera eases in and out instead of jerking
+        self.from.blend(&self.to, t * t * (3.0 - 2.0 * t))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+}
@@ -0,0 +1,63 @@
+use macaw::{Vec2, vec2};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use crate::poke::PokeSettings;
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RaindropSettings {
+    pub enabled: bool,
+    /// Ticks between drops
+    pub delay: u32,
+    pub poke: PokeSettings,
+}
+
+impl Default for RaindropSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            delay: 250,
+            poke: PokeSettings::default(),
+        }
+    }
+}
+
+/// Drops pokes on random spots of the water. Driven by a seeded RNG so the same seed always
+/// gives the same rain.
+pub struct Raindrops {
+    pub settings: RaindropSettings,
+    ticks: u32,
+    // ChaCha8 rather than StdRng, its output is guaranteed not to change between rand versions
+    rng: ChaCha8Rng,
+}
+
+impl Raindrops {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            settings: RaindropSettings::default(),
+            ticks: 0,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+
+    /// Starts the rain over from the beginning of the sequence for `seed`
+    pub fn reseed(&mut self, seed: u64) {
+        self.ticks = 0;
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    /// Call once per simulation tick. Returns where the next drop lands in the 0..1 range, if
+    /// one is due.
+    pub fn tick(&mut self) -> Option<Vec2> {
+        if !self.settings.enabled {
+            return None;
+        }
+        self.ticks += 1;
+        if self.ticks < self.settings.delay {
+            return None;
+        }
+        self.ticks = 0;
+        Some(vec2(self.rng.random(), self.rng.random()))
+    }
+}
@@ -0,0 +1,308 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use macaw::{UVec2, Vec2};
+use serde::{Deserialize, Serialize};
+use crate::brush::Brush;
+use crate::emitter::Emitter;
+use crate::poke::Poke;
+use crate::simulation::WavePoint;
+use crate::snapshot::Snapshot;
+use crate::solver::{SimParams, SolverKind, WaveSolver};
+
+const MAGIC: &[u8; 4] = b"RREC";
+pub const VERSION: u32 = 1;
+
+/// Everything from the settings window that changes how the field evolves
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Settings {
+    pub params: SimParams,
+    pub emitters: Vec<Emitter>,
+}
+
+/// Something the user did to the simulation. Applied between ticks, right before the tick
+/// it was recorded at. Raindrops are recorded as pokes, so replays don't depend on the seed.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum InputEvent {
+    Poke { poke: Poke, center: Vec2 },
+    Paint { brush: Brush, center: Vec2 },
+    ClearMedium,
+    Reset,
+    Resize(UVec2),
+    SetSolver(SolverKind),
+    Settings(Settings),
+}
+
+/// A run that can be played back to get the exact same field again
+pub struct Recording {
+    /// Field the run started from
+    pub start: Snapshot,
+    pub solver: SolverKind,
+    /// Events in the order they happened, with the tick they happened before
+    pub events: Vec<(u64, InputEvent)>,
+    pub end_tick: u64,
+    /// `checksum` of the field at `end_tick`
+    pub checksum: u64,
+}
+
+/// The part of a recording file after the snapshot, stored as RON
+#[derive(Serialize, Deserialize)]
+struct EventLog {
+    solver: SolverKind,
+    end_tick: u64,
+    checksum: u64,
+    events: Vec<(u64, InputEvent)>,
+}
+
+impl Recording {
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        self.start.write(&mut writer)?;
+        let log = EventLog {
+            solver: self.solver,
+            end_tick: self.end_tick,
+            checksum: self.checksum,
+            events: self.events.clone(),
+        };
+        let text = ron::ser::to_string_pretty(&log, ron::ser::PrettyConfig::default())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        writer.write_all(text.as_bytes())?;
+        writer.flush()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut header = [0; 8];
+        reader.read_exact(&mut header)?;
+        if &header[..4] != MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "not a ripple recording"));
+        }
+        let version = u32::from_le_bytes(header[4..].try_into().unwrap());
+        if version != VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported recording version {}, expected {}", version, VERSION),
+            ));
+        }
+        let start = Snapshot::read(&mut reader)?;
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        let log: EventLog = ron::from_str(&text)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        Ok(Self {
+            start,
+            solver: log.solver,
+            events: log.events,
+            end_tick: log.end_tick,
+            checksum: log.checksum,
+        })
+    }
+}
+
+/// Collects events while the user plays with the water
+pub struct Recorder {
+    recording: Recording,
+    settings: Settings,
+}
+
+impl Recorder {
+    /// Starts from the current field
+    pub fn start(solver: &dyn WaveSolver, tick: u64, settings: Settings) -> Self {
+        let mut recorder = Self {
+            recording: Recording {
+                start: Snapshot::capture(solver, tick),
+                solver: solver.kind(),
+                events: Vec::new(),
+                end_tick: tick,
+                checksum: 0,
+            },
+            settings: settings.clone(),
+        };
+        recorder.record(tick, InputEvent::Settings(settings));
+        recorder
+    }
+
+    pub fn record(&mut self, tick: u64, event: InputEvent) {
+        self.recording.events.push((tick, event));
+    }
+
+    /// Records the settings if they changed since the last call
+    pub fn record_settings(&mut self, tick: u64, settings: &Settings) {
+        if *settings != self.settings {
+            self.settings = settings.clone();
+            self.record(tick, InputEvent::Settings(settings.clone()));
+        }
+    }
+
+    pub fn finish(self, solver: &dyn WaveSolver, tick: u64) -> Recording {
+        Recording {
+            end_tick: tick,
+            checksum: checksum(&solver.export_points()),
+            ..self.recording
+        }
+    }
+}
+
+/// Feeds a recording back in, tick by tick
+pub struct Replay {
+    recording: Recording,
+    next_event: usize,
+}
+
+impl Replay {
+    pub fn new(recording: Recording) -> Self {
+        Self {
+            recording,
+            next_event: 0,
+        }
+    }
+
+    /// Events to apply before running `tick`
+    pub fn events_due(&mut self, tick: u64) -> Vec<InputEvent> {
+        let mut due = Vec::new();
+        while let Some((event_tick, event)) = self.recording.events.get(self.next_event) {
+            if *event_tick > tick {
+                break;
+            }
+            due.push(event.clone());
+            self.next_event += 1;
+        }
+        due
+    }
+
+    /// Hands the events due before `tick` to `apply`, then returns whether `tick` should still
+    /// run. Events logged on the end tick happened before the recording stopped, so they're
+    /// applied before the replay is called finished.
+    pub fn step<E>(&mut self, tick: u64, mut apply: impl FnMut(InputEvent) -> Result<(), E>) -> Result<bool, E> {
+        for event in self.events_due(tick) {
+            apply(event)?;
+        }
+        Ok(!self.is_finished(tick))
+    }
+
+    pub fn is_finished(&self, tick: u64) -> bool {
+        tick >= self.recording.end_tick
+    }
+
+    /// Whether `solver` ended up with the exact same field as the recorded run
+    pub fn matches(&self, solver: &dyn WaveSolver) -> bool {
+        checksum(&solver.export_points()) == self.recording.checksum
+    }
+}
+
+/// FNV-1a over the raw bits of the field, any difference at all changes it
+pub fn checksum(points: &[WavePoint]) -> u64 {
+    let bytes: &[u8] = bytemuck::cast_slice(points);
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macaw::{uvec2, vec2};
+    use crate::poke::PokeShape;
+    use crate::simulation::WaveSimulation;
+
+    fn poke(amplitude: f32) -> InputEvent {
+        InputEvent::Poke {
+            poke: Poke { shape: PokeShape::Box, radius: 2.0, amplitude },
+            center: vec2(4.0, 4.0),
+        }
+    }
+
+    fn replay(events: Vec<(u64, InputEvent)>, end_tick: u64) -> Replay {
+        let solver = WaveSimulation::new(uvec2(8, 8));
+        Replay::new(Recording {
+            start: Snapshot::capture(&solver, 0),
+            solver: SolverKind::Cpu,
+            events,
+            end_tick,
+            checksum: 0,
+        })
+    }
+
+    #[test]
+    fn checksum_sees_any_change() {
+        let mut points = vec![WavePoint::default(); 16];
+        let before = checksum(&points);
+        assert_eq!(checksum(&points.clone()), before);
+        points[7].velocity = f32::from_bits(points[7].velocity.to_bits() + 1);
+        assert_ne!(checksum(&points), before);
+    }
+
+    #[test]
+    fn events_come_out_on_their_tick() {
+        let mut replay = replay(vec![(0, poke(1.0)), (2, poke(2.0)), (2, poke(3.0)), (5, InputEvent::Reset)], 10);
+        assert_eq!(replay.events_due(0), [poke(1.0)]);
+        assert_eq!(replay.events_due(1), []);
+        assert_eq!(replay.events_due(2), [poke(2.0), poke(3.0)]);
+        // Anything missed is caught up on
+        assert_eq!(replay.events_due(7), [InputEvent::Reset]);
+        assert_eq!(replay.events_due(8), []);
+    }
+
+    #[test]
+    fn step_applies_events_on_the_end_tick() {
+        let mut replay = replay(vec![(1, poke(1.0)), (3, poke(2.0))], 3);
+        let mut applied = Vec::new();
+        let mut step = |tick| replay.step(tick, |event| {
+            applied.push((tick, event));
+            Ok::<_, ()>(())
+        });
+        assert_eq!(step(0), Ok(true));
+        assert_eq!(step(1), Ok(true));
+        assert_eq!(step(2), Ok(true));
+        assert_eq!(step(3), Ok(false));
+        assert_eq!(applied, [(1, poke(1.0)), (3, poke(2.0))]);
+    }
+
+    #[test]
+    fn step_stops_on_the_first_error() {
+        let mut replay = replay(vec![(0, poke(1.0)), (0, poke(2.0))], 3);
+        let mut applied = 0;
+        let result = replay.step(0, |_| {
+            applied += 1;
+            Err("no GPU")
+        });
+        assert_eq!(result, Err("no GPU"));
+        assert_eq!(applied, 1);
+    }
+
+    #[test]
+    fn replaying_a_recording_matches_it() {
+        let mut solver = WaveSimulation::new(uvec2(8, 8));
+        let mut recorder = Recorder::start(&solver, 0, Settings { params: SimParams::default(), emitters: Vec::new() });
+        for tick in 0..4 {
+            if tick == 1 {
+                recorder.record(tick, poke(1.0));
+                solver.poke(&Poke { shape: PokeShape::Box, radius: 2.0, amplitude: 1.0 }, vec2(4.0, 4.0));
+            }
+            solver.advance();
+        }
+        // Logged right before stopping, after the last tick ran
+        recorder.record(4, poke(-0.5));
+        solver.poke(&Poke { shape: PokeShape::Box, radius: 2.0, amplitude: -0.5 }, vec2(4.0, 4.0));
+        let recording = recorder.finish(&solver, 4);
+
+        let mut replayed = WaveSimulation::new(uvec2(8, 8));
+        recording.start.restore(&mut replayed);
+        let mut replay = Replay::new(recording);
+        let mut tick = 0;
+        while replay.step(tick, |event| {
+            let InputEvent::Poke { poke, center } = event else {
+                return Ok::<_, ()>(());
+            };
+            replayed.poke(&poke, center);
+            Ok(())
+        }).unwrap() {
+            replayed.advance();
+            tick += 1;
+        }
+        assert_eq!(tick, 4);
+        assert!(replay.matches(&replayed));
+    }
+}
@@ -0,0 +1,304 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::sync::mpsc;
+use macaw::UVec2;
+use winit::window::Window;
+use crate::sim_renderer::Antialiasing;
+use crate::texture;
+
+/// Where `GfxState` draws its frames
+pub enum RenderTarget<'a> {
+    /// Presented to a window, with the UI drawn on top
+    Window {
+        surface: wgpu::Surface<'a>,
+        config: wgpu::SurfaceConfiguration,
+        scene: SceneTextures,
+    },
+    /// Kept in a texture so it can be read back, see `OffscreenTarget::read`
+    Offscreen(OffscreenTarget),
+}
+
+impl RenderTarget<'_> {
+    pub fn format(&self) -> wgpu::TextureFormat {
+        match self {
+            RenderTarget::Window { config, .. } => config.format,
+            RenderTarget::Offscreen(target) => target.format,
+        }
+    }
+
+    pub fn scene(&self) -> &SceneTextures {
+        match self {
+            RenderTarget::Window { scene, .. } => scene,
+            RenderTarget::Offscreen(target) => &target.scene,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, size: UVec2) {
+        match self {
+            RenderTarget::Window { surface, config, scene } => {
+                config.width = size.x;
+                config.height = size.y;
+                surface.configure(device, config);
+                *scene = SceneTextures::new(device, size, config.format, scene.antialiasing);
+            }
+            RenderTarget::Offscreen(target) => {
+                *target = OffscreenTarget::new(device, size, target.format, target.scene.antialiasing)
+            }
+        }
+    }
+
+    /// Rebuilds the scene textures for `antialiasing`, the sim renderer's sample count has to be
+    /// changed to match
+    pub fn set_antialiasing(&mut self, device: &wgpu::Device, antialiasing: Antialiasing) {
+        let format = self.format();
+        match self {
+            RenderTarget::Window { config, scene, .. } => {
+                *scene = SceneTextures::new(device, UVec2::new(config.width, config.height), format, antialiasing);
+            }
+            RenderTarget::Offscreen(target) => {
+                target.scene = SceneTextures::new(device, target.size, format, antialiasing);
+            }
+        }
+    }
+}
+
+/// What the prisms are drawn into before they reach the target
+pub struct SceneTextures {
+    pub antialiasing: Antialiasing,
+    /// Has as many samples per pixel as the antialiasing calls for
+    pub depth: texture::Texture,
+    /// Multisampled colour resolved into the target with MSAA, or the image FXAA reads from.
+    /// `None` without antialiasing, the prisms go straight to the target.
+    pub color: Option<wgpu::TextureView>,
+}
+
+impl SceneTextures {
+    pub fn new(device: &wgpu::Device, size: UVec2, format: wgpu::TextureFormat, antialiasing: Antialiasing) -> Self {
+        let sample_count = antialiasing.sample_count();
+        let depth = texture::Texture::create_multisampled_depth_texture(
+            device,
+            size.x,
+            size.y,
+            sample_count,
+            "scene depth texture",
+        );
+        let usage = match antialiasing {
+            Antialiasing::Off => None,
+            Antialiasing::Fxaa => Some(wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING),
+            _ => Some(wgpu::TextureUsages::RENDER_ATTACHMENT),
+        };
+        let color = usage.map(|usage| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("scene colour texture"),
+                size: wgpu::Extent3d {
+                    width: size.x.max(1),
+                    height: size.y.max(1),
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage,
+                view_formats: &[],
+            }).create_view(&wgpu::TextureViewDescriptor::default())
+        });
+        Self {
+            antialiasing,
+            depth,
+            color,
+        }
+    }
+}
+
+/// Creates the surface configuration for `window`, preferring an sRGB format
+pub fn window_config(
+    surface: &wgpu::Surface,
+    adapter: &wgpu::Adapter,
+    window: &Window,
+    present_mode: Option<wgpu::PresentMode>,
+) -> wgpu::SurfaceConfiguration {
+    let size = window.inner_size();
+    let surface_caps = surface.get_capabilities(adapter);
+    let present_mode = match present_mode {
+        // The auto modes are always accepted and fall back by themselves
+        Some(mode @ (wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync)) => mode,
+        Some(mode) if surface_caps.present_modes.contains(&mode) => mode,
+        Some(mode) => {
+            log::warn!("Present mode {:?} isn't supported, using {:?}", mode, surface_caps.present_modes[0]);
+            surface_caps.present_modes[0]
+        }
+        None => surface_caps.present_modes[0],
+    };
+    let surface_format = surface_caps.formats.iter()
+        .find(|f| f.is_srgb())
+        .copied()
+        .unwrap_or(surface_caps.formats[0]);
+    wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format: surface_format,
+        width: size.width,
+        height: size.height,
+        present_mode,
+        alpha_mode: surface_caps.alpha_modes[0],
+        view_formats: vec![],
+        desired_maximum_frame_latency: 2,
+    }
+}
+
+/// A colour texture and its scene textures to draw into without a window
+pub struct OffscreenTarget {
+    pub size: UVec2,
+    pub format: wgpu::TextureFormat,
+    texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub scene: SceneTextures,
+}
+
+impl OffscreenTarget {
+    /// Used when there's no window format to match
+    pub const DEFAULT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    /// `format` has to be 8 bits per channel RGBA or BGRA so `read` can make sense of it
+    pub fn new(device: &wgpu::Device, size: UVec2, format: wgpu::TextureFormat, antialiasing: Antialiasing) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offscreen target"),
+            size: wgpu::Extent3d {
+                width: size.x,
+                height: size.y,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            // Sampled when copying into a readable format, see `GfxState::capture`
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_SRC
+                | wgpu::TextureUsages::TEXTURE_BINDING,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            size,
+            format,
+            texture,
+            view,
+            scene: SceneTextures::new(device, size, format, antialiasing),
+        }
+    }
+
+    /// Copies the image back to the CPU. Waits for everything submitted so far to finish.
+    pub fn read(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Frame {
+        // Rows in the copy have to start on a 256 byte boundary
+        let row_bytes = 4 * self.size.x;
+        let padded_row_bytes = row_bytes.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+            * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("offscreen readback"),
+            size: (padded_row_bytes * self.size.y) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+        let mut encoder = device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("offscreen readback encoder") }
+        );
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_bytes),
+                    rows_per_image: Some(self.size.y),
+                },
+            },
+            self.texture.size(),
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        let (sender, receiver) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| sender.send(result).unwrap());
+        device.poll(wgpu::Maintain::Wait);
+        receiver.recv().unwrap().expect("couldn't map the offscreen readback buffer");
+
+        let mut pixels = Vec::with_capacity((row_bytes * self.size.y) as usize);
+        for row in slice.get_mapped_range().chunks(padded_row_bytes as usize) {
+            pixels.extend_from_slice(&row[..row_bytes as usize]);
+        }
+        buffer.unmap();
+        if matches!(self.format, wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb) {
+            for pixel in pixels.chunks_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+        Frame { size: self.size, pixels }
+    }
+}
+
+/// An image read back from the GPU
+pub struct Frame {
+    pub size: UVec2,
+    /// RGBA, 8 bits per channel, rows from the top
+    pub pixels: Vec<u8>,
+}
+
+impl Frame {
+    /// Averages each `factor` by `factor` block into one pixel. Blends in linear space so edges
+    /// don't come out too dark.
+    pub fn downsample(&self, factor: u32) -> Frame {
+        let size = self.size / factor;
+        let mut pixels = Vec::with_capacity((size.x * size.y * 4) as usize);
+        let samples = (factor * factor) as f32;
+        for y in 0..size.y {
+            for x in 0..size.x {
+                let mut sum = [0.0f32; 4];
+                for sample_y in y * factor..(y + 1) * factor {
+                    for sample_x in x * factor..(x + 1) * factor {
+                        let index = ((sample_y * self.size.x + sample_x) * 4) as usize;
+                        for (channel, total) in sum.iter_mut().enumerate() {
+                            let value = self.pixels[index + channel] as f32 / 255.0;
+                            *total += if channel < 3 { srgb_to_linear(value) } else { value };
+                        }
+                    }
+                }
+                for (channel, total) in sum.into_iter().enumerate() {
+                    let value = total / samples;
+                    let value = if channel < 3 { linear_to_srgb(value) } else { value };
+                    pixels.push((value * 255.0).round() as u8);
+                }
+            }
+        }
+        Frame { size, pixels }
+    }
+
+    pub fn save_png(&self, path: &Path) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), self.size.x, self.size.y);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        // The targets are sRGB, so the bytes already are what should end up on screen
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
@@ -1,32 +1,103 @@
 use std::sync::Arc;
 use egui_wgpu::ScreenDescriptor;
+use macaw::{UVec2, Vec2};
 use winit::window::Window;
-use crate::camera::Projection;
-use crate::{mesh, mesh_grid};
+use crate::camera::{Camera, Projection};
+use crate::{mesh, mesh_grid, render_target, texture};
 use crate::egui_renderer::EguiRenderer;
-use crate::sim_renderer::SimRenderer;
-use crate::simulation::DIVISIONS;
+use crate::lighting::Lighting;
+use crate::render_target::{Frame, OffscreenTarget, RenderTarget, SceneTextures};
+use crate::sim_renderer::{Antialiasing, SimRenderer};
+use crate::solver::SolverOutput;
+
+/// Features every device has to support, the sim texture is sampled with linear filtering
+pub const REQUIRED_FEATURES: wgpu::Features = wgpu::Features::FLOAT32_FILTERABLE;
+
+/// Device for running without a window, e.g. for batch runs on machines without a display.
+/// `force_fallback_adapter` picks a software adapter where there is one.
+pub async fn request_headless_device(
+    force_fallback_adapter: bool,
+) -> Option<(Arc<wgpu::Device>, Arc<wgpu::Queue>)> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::PRIMARY,
+        ..Default::default()
+    });
+    let adapter = instance.request_adapter(
+        &wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: None,
+            force_fallback_adapter,
+        }
+    ).await?;
+    let (device, queue) = adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            required_features: REQUIRED_FEATURES,
+            required_limits: Default::default(),
+            label: None,
+            memory_hints: Default::default(),
+        },
+        None,
+    ).await.ok()?;
+    Some((Arc::new(device), Arc::new(queue)))
+}
+
+/// Names for `wgpu::PresentMode` on the command line, the auto modes pick whatever the
+/// platform supports
+pub const PRESENT_MODES: [(&str, wgpu::PresentMode); 5] = [
+    ("auto-vsync", wgpu::PresentMode::AutoVsync),
+    ("auto-no-vsync", wgpu::PresentMode::AutoNoVsync),
+    ("fifo", wgpu::PresentMode::Fifo),
+    ("mailbox", wgpu::PresentMode::Mailbox),
+    ("immediate", wgpu::PresentMode::Immediate),
+];
+
+pub fn present_mode_from_name(name: &str) -> Option<wgpu::PresentMode> {
+    PRESENT_MODES.into_iter()
+        .find(|(mode_name, _)| mode_name.eq_ignore_ascii_case(name))
+        .map(|(_, mode)| mode)
+}
+
+/// The antialiasing `adapter` can draw into `format` with. Without an adapter, or one without
+/// `TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES`, only the sample counts every device has are used.
+fn supported_antialiasing(adapter: Option<&wgpu::Adapter>, format: wgpu::TextureFormat) -> Vec<Antialiasing> {
+    let adapter = adapter.filter(|adapter| {
+        adapter.features().contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES)
+    });
+    Antialiasing::ALL.into_iter()
+        .filter(|antialiasing| match (antialiasing.sample_count(), adapter) {
+            (1, _) => true,
+            (count, Some(adapter)) => [format, texture::Texture::DEPTH_FORMAT].into_iter()
+                .all(|format| adapter.get_texture_format_features(format).flags.sample_count_supported(count)),
+            (count, None) => count == 4,
+        })
+        .collect()
+}
 
 pub struct GfxState<'a> {
-    surface: wgpu::Surface<'a>,
-    pub(crate) device: wgpu::Device,
-    pub(crate) queue: wgpu::Queue,
-    config: wgpu::SurfaceConfiguration,
+    target: RenderTarget<'a>,
+    pub(crate) device: Arc<wgpu::Device>,
+    pub(crate) queue: Arc<wgpu::Queue>,
     pub size: winit::dpi::PhysicalSize<u32>,
-    pub window: Arc<Window>,
+    /// Only set when drawing to a window
+    window: Option<Arc<Window>>,
 
     pub(crate) projection: Projection,
 
     pub sim: SimRenderer,
-    pub egui_renderer: EguiRenderer,
+    egui_renderer: Option<EguiRenderer>,
+    /// See `set_antialiasing`
+    supported_antialiasing: Vec<Antialiasing>,
 }
 
 impl<'a> GfxState<'a> {
+    /// `present_mode` falls back to the first one the surface supports if left out or unsupported
     pub(crate) async fn new(
         window: Arc<Window>,
+        present_mode: Option<wgpu::PresentMode>,
         fov_y: f32,
         prism: &mesh::Mesh,
         grid: &mesh_grid::MeshGrid,
+        sim_size: UVec2,
     ) -> GfxState<'a> {
         let size = window.inner_size();
 
@@ -44,86 +115,195 @@ impl<'a> GfxState<'a> {
         ).await.unwrap();
         let (device, queue) = adapter.request_device(
             &wgpu::DeviceDescriptor {
-                required_features: wgpu::Features::FLOAT32_FILTERABLE,
+                // Lets the adapter's own sample counts be used, see `supported_antialiasing`
+                required_features: REQUIRED_FEATURES
+                    | (adapter.features() & wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES),
                 required_limits: Default::default(),
                 label: None,
                 memory_hints: Default::default(),
             },
             None,
         ).await.unwrap();
-        let surface_caps = surface.get_capabilities(&adapter);
-        let surface_format = surface_caps.formats.iter()
-            .find(|f| f.is_srgb())
-            .copied()
-            .unwrap_or(surface_caps.formats[0]);
-        let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface_format,
-            width: size.width,
-            height: size.height,
-            present_mode: surface_caps.present_modes[0],
-            alpha_mode: surface_caps.alpha_modes[0],
-            view_formats: vec![],
-            desired_maximum_frame_latency: 2,
-        };
+        let config = render_target::window_config(&surface, &adapter, &window, present_mode);
+        let supported_antialiasing = supported_antialiasing(Some(&adapter), config.format);
 
         let egui_renderer = EguiRenderer::new(&device, config.format, None, 1, &window);
         let projection = Projection::new(size.width, size.height, fov_y, 0.1, 10000.0);
 
         surface.configure(&device, &config);
-        let sim = SimRenderer::new(&device, &config, &prism, &grid, DIVISIONS);
+        let scene = SceneTextures::new(&device, UVec2::new(size.width, size.height), config.format, Antialiasing::Off);
+        let sim = SimRenderer::new(&device, &queue, config.format, prism, grid, sim_size);
 
         Self {
-            window,
-            surface,
-            device,
-            queue,
-            config,
+            window: Some(window),
+            target: RenderTarget::Window { surface, config, scene },
+            device: Arc::new(device),
+            queue: Arc::new(queue),
             size,
 
             projection,
 
             sim,
-            egui_renderer,
+            egui_renderer: Some(egui_renderer),
+            supported_antialiasing,
+        }
+    }
+
+    /// Draws into a texture instead of a window, see `read_frame`. There's no UI.
+    pub fn new_offscreen(
+        device: Arc<wgpu::Device>,
+        queue: Arc<wgpu::Queue>,
+        size: UVec2,
+        fov_y: f32,
+        prism: &mesh::Mesh,
+        grid: &mesh_grid::MeshGrid,
+        sim_size: UVec2,
+    ) -> GfxState<'a> {
+        let target = OffscreenTarget::new(&device, size, OffscreenTarget::DEFAULT_FORMAT, Antialiasing::Off);
+        let sim = SimRenderer::new(&device, &queue, target.format, prism, grid, sim_size);
+        let supported_antialiasing = supported_antialiasing(None, target.format);
+        Self {
+            window: None,
+            target: RenderTarget::Offscreen(target),
+            device,
+            queue,
+            size: winit::dpi::PhysicalSize::new(size.x, size.y),
+
+            projection: Projection::new(size.x, size.y, fov_y, 0.1, 10000.0),
+
+            sim,
+            egui_renderer: None,
+            supported_antialiasing,
+        }
+    }
+
+    pub fn egui_renderer(&mut self) -> &mut EguiRenderer {
+        self.egui_renderer.as_mut().expect("offscreen GfxState has no UI")
+    }
+
+    pub fn antialiasing(&self) -> Antialiasing {
+        self.target.scene().antialiasing
+    }
+
+    /// Modes `set_antialiasing` can pick without falling back
+    pub fn supported_antialiasing(&self) -> &[Antialiasing] {
+        &self.supported_antialiasing
+    }
+
+    /// Switches how the prism edges are smoothed. Falls back to FXAA if the adapter can't draw
+    /// with that many samples, returns what it ended up using.
+    pub fn set_antialiasing(&mut self, antialiasing: Antialiasing) -> Antialiasing {
+        let antialiasing = if self.supported_antialiasing.contains(&antialiasing) {
+            antialiasing
+        } else {
+            log::warn!("{} isn't supported, using {}", antialiasing.name(), Antialiasing::Fxaa.name());
+            Antialiasing::Fxaa
+        };
+        if antialiasing != self.antialiasing() {
+            self.sim.set_sample_count(&self.device, antialiasing.sample_count());
+            self.target.set_antialiasing(&self.device, antialiasing);
         }
+        antialiasing
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+            self.target.resize(&self.device, UVec2::new(new_size.width, new_size.height));
             self.projection.resize(new_size.width, new_size.height);
-            self.sim.resize(&self.device, &self.config);
         }
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        let output = self.surface.get_current_texture()?;
-        let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
         let mut encoder = self.device.create_command_encoder(
             &wgpu::CommandEncoderDescriptor { label: Some("Render Encoder") }
         );
-        self.sim.render(&view, &mut encoder);
-        self.render_egui(&view, &mut encoder);
+        let output = match &self.target {
+            RenderTarget::Window { surface, scene, .. } => {
+                let output = surface.get_current_texture()?;
+                let view = output.texture.create_view(&wgpu::TextureViewDescriptor::default());
+                self.sim.render(&self.device, &view, scene, &mut encoder);
+                Some((output, view))
+            }
+            RenderTarget::Offscreen(target) => {
+                self.sim.render(&self.device, &target.view, &target.scene, &mut encoder);
+                None
+            }
+        };
+        if let Some((_, view)) = &output {
+            self.render_egui(view, &mut encoder);
+        }
         self.queue.submit(std::iter::once(encoder.finish()));
 
-        output.present();
+        if let Some((output, _)) = output {
+            output.present();
+        }
         Ok(())
     }
 
+    /// Hands the solver's latest field to the sim renderer, `alpha` is as in
+    /// `SimRenderer::set_interpolation`
+    pub fn update_field(&self, output: SolverOutput, alpha: f32) {
+        match output {
+            SolverOutput::Points { size, current, previous } => {
+                self.sim.update_sim_data(&self.queue, size, current, previous);
+            }
+            SolverOutput::Texture { current, previous } => {
+                self.sim.copy_sim_texture(&self.device, &self.queue, current, previous);
+            }
+        }
+        self.sim.set_interpolation(&self.queue, alpha);
+    }
+
+    pub fn set_camera(&self, camera: &Camera) {
+        let camera_transform = self.projection.calc_matrix() * camera.calc_matrix();
+        self.sim.set_camera_transform(&self.queue, camera_transform, camera.position);
+    }
+
+    pub fn set_lighting(&mut self, lighting: &Lighting, grid_extent: Vec2, prism_height: f32) {
+        self.sim.set_lighting(&self.queue, lighting, grid_extent, prism_height);
+    }
+
+    /// Draws the field as last passed to `update_field` into a new image of `size`, without the
+    /// UI. Leaves the camera transform set for `size`, so call `set_camera` before the next frame.
+    pub fn capture(&mut self, size: UVec2, camera: &Camera) -> Frame {
+        // Drawn in the window's format, which the pipelines are built for, then copied into one
+        // `read` understands. Window formats are sRGB wherever there is one, see `window_config`.
+        let scene = OffscreenTarget::new(&self.device, size, self.target.format(), self.antialiasing());
+        let target = OffscreenTarget::new(&self.device, size, OffscreenTarget::DEFAULT_FORMAT, Antialiasing::Off);
+        let mut projection = self.projection.clone();
+        projection.resize(size.x, size.y);
+        self.sim.set_camera_transform(&self.queue, projection.calc_matrix() * camera.calc_matrix(), camera.position);
+        let mut encoder = self.device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("Capture Encoder") }
+        );
+        self.sim.render(&self.device, &scene.view, &scene.scene, &mut encoder);
+        self.sim.copy_to_readable(&self.device, &scene.view, &target.view, &mut encoder);
+        self.queue.submit(std::iter::once(encoder.finish()));
+        target.read(&self.device, &self.queue)
+    }
+
+    /// Copies the last frame back from an offscreen target, `None` when drawing to a window
+    pub fn read_frame(&self) -> Option<Frame> {
+        match &self.target {
+            RenderTarget::Window { .. } => None,
+            RenderTarget::Offscreen(target) => Some(target.read(&self.device, &self.queue)),
+        }
+    }
+
     fn render_egui(
         &mut self,
         surface_view: &wgpu::TextureView,
         encoder: &mut wgpu::CommandEncoder,
     ) {
+        let (Some(window), Some(egui_renderer)) = (&self.window, &mut self.egui_renderer) else {
+            return;
+        };
         let screen_descriptor = ScreenDescriptor {
             size_in_pixels: [self.size.width, self.size.height],
-            pixels_per_point: self.window.scale_factor() as f32,
+            pixels_per_point: window.scale_factor() as f32,
         };
-        let window = self.window.as_ref();
-        self.egui_renderer.end_frame_and_draw(
+        egui_renderer.end_frame_and_draw(
             &self.device,
             &self.queue,
             encoder,
@@ -132,4 +312,4 @@ impl<'a> GfxState<'a> {
             screen_descriptor,
         );
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,141 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use macaw::{UVec2, Vec2, vec2};
+use serde::{Deserialize, Serialize};
+use crate::brush::{self, WALL};
+use crate::camera::CameraConfig;
+use crate::emitter::Emitter;
+use crate::lighting::Lighting;
+use crate::raindrops::RaindropSettings;
+use crate::sim_renderer::RenderConfig;
+use crate::simulation::{self, WavePoint};
+use crate::solver::{SimParams, Source, WaveSolver};
+
+/// A region of the grid. Positions are in the 0..1 range like emitters, sizes are in cells.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Shape {
+    Circle { center: Vec2, radius: f32 },
+    Rect { min: Vec2, max: Vec2 },
+    /// Every cell within `radius` of the segment, e.g. a thin wall
+    Line { start: Vec2, end: Vec2, radius: f32 },
+}
+
+impl Shape {
+    /// Whether the cell with its center at `point`, in cells, is inside
+    pub fn contains(&self, point: Vec2, size: UVec2) -> bool {
+        let size = size.as_vec2();
+        match *self {
+            Shape::Circle { center, radius } => point.distance(center * size) <= radius,
+            Shape::Rect { min, max } => {
+                point.cmpge(min * size).all() && point.cmple(max * size).all()
+            }
+            Shape::Line { start, end, radius } => {
+                Source { start: start * size, end: end * size, radius, value: 0.0 }.covers(point)
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Region {
+    Wall(Shape),
+    /// Slow (< 1) or fast (> 1) water, see `BrushTool::Medium`
+    Medium { shape: Shape, medium: f32 },
+}
+
+impl Region {
+    pub fn paint(&self, points: &mut [WavePoint], size: UVec2) {
+        let (shape, medium) = match self {
+            Region::Wall(shape) => (shape, WALL),
+            Region::Medium { shape, medium } => (shape, *medium),
+        };
+        for y in 0..size.y {
+            for x in 0..size.x {
+                if !shape.contains(vec2(x as f32 + 0.5, y as f32 + 0.5), size) {
+                    continue;
+                }
+                let point = &mut points[(y * size.x + x) as usize];
+                point.medium = medium;
+                if medium < 0.0 {
+                    point.value = 0.0;
+                    point.velocity = 0.0;
+                }
+            }
+        }
+    }
+}
+
+/// Everything needed to set a run up, kept in a RON file so it can be written by hand. Anything
+/// left out of the file keeps its default.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Scene {
+    /// Grid size in cells
+    pub size: UVec2,
+    pub params: SimParams,
+    /// Painted in order onto open water, so later regions cover earlier ones
+    pub regions: Vec<Region>,
+    pub emitters: Vec<Emitter>,
+    pub raindrops: RaindropSettings,
+    pub camera: CameraConfig,
+    pub render: RenderConfig,
+    pub lighting: Lighting,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self {
+            size: simulation::DEFAULT_SIZE,
+            params: SimParams::default(),
+            regions: Vec::new(),
+            emitters: Vec::new(),
+            raindrops: RaindropSettings::default(),
+            camera: CameraConfig::default(),
+            render: RenderConfig::default(),
+            lighting: Lighting::default(),
+        }
+    }
+}
+
+impl Scene {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let scene: Scene = ron::from_str(&fs::read_to_string(path)?)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        simulation::check_size(scene.size).map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        Ok(scene)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        fs::write(path, text)
+    }
+
+    /// Sets `solver` up with calm water at the scene's size, parameters and regions. The rest of
+    /// the scene is up to the caller.
+    pub fn apply_field(&self, solver: &mut dyn WaveSolver) {
+        solver.resize(self.size);
+        *solver.params_mut() = self.params;
+        solver.reset();
+        let mut points = solver.export_points();
+        brush::clear_medium(&mut points);
+        for region in &self.regions {
+            region.paint(&mut points, self.size);
+        }
+        solver.import_points(&points);
+    }
+}
+
+/// Scene files in `directory`, sorted by name
+pub fn list(directory: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(directory) else {
+        return Vec::new();
+    };
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "ron"))
+        .collect();
+    paths.sort();
+    paths
+}
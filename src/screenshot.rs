@@ -0,0 +1,80 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+
+/// How many times the window resolution a screenshot is drawn at
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum ScreenshotScale {
+    One,
+    Two,
+    Four,
+}
+
+impl ScreenshotScale {
+    pub const ALL: [ScreenshotScale; 3] = [ScreenshotScale::One, ScreenshotScale::Two, ScreenshotScale::Four];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScreenshotScale::One => "1×",
+            ScreenshotScale::Two => "2×",
+            ScreenshotScale::Four => "4×",
+        }
+    }
+
+    pub fn factor(&self) -> u32 {
+        match self {
+            ScreenshotScale::One => 1,
+            ScreenshotScale::Two => 2,
+            ScreenshotScale::Four => 4,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ScreenshotSettings {
+    pub scale: ScreenshotScale,
+    /// Averages the bigger image back down to the window size, which smooths the prism edges
+    pub downsample: bool,
+    pub directory: String,
+}
+
+impl Default for ScreenshotSettings {
+    fn default() -> Self {
+        Self {
+            scale: ScreenshotScale::Two,
+            downsample: false,
+            directory: "screenshots".into(),
+        }
+    }
+}
+
+impl ScreenshotSettings {
+    /// Where a screenshot taken at `time` goes, e.g.
+    /// `screenshots/ripple_2024-11-30_18-04-59-250.png`
+    pub fn path(&self, time: SystemTime) -> PathBuf {
+        Path::new(&self.directory).join(format!("ripple_{}.png", timestamp(time)))
+    }
+}
+
+/// UTC date and time, down to the millisecond so quick captures don't overwrite each other
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (days, second_of_day) = (seconds / 86400, seconds % 86400);
+    // Days since the epoch to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}_{:02}-{:02}-{:02}-{:03}",
+        year, month, day, second_of_day / 3600, second_of_day / 60 % 60, second_of_day % 60,
+        since_epoch.subsec_millis(),
+    )
+}
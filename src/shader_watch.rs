@@ -0,0 +1,48 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use crate::sim_renderer::RenderMode;
+
+/// Loads the render shaders from a directory and picks them up again whenever they're saved,
+/// so they can be tweaked without rebuilding. Polls modification times, which is cheap enough
+/// to do every frame for a couple of files.
+pub struct ShaderWatcher {
+    directory: PathBuf,
+    /// When each shader was modified as of the last load, `None` until it has been loaded
+    loaded: Vec<(RenderMode, Option<SystemTime>)>,
+}
+
+impl ShaderWatcher {
+    pub fn new(directory: PathBuf) -> Self {
+        Self {
+            directory,
+            loaded: RenderMode::ALL.into_iter().map(|mode| (mode, None)).collect(),
+        }
+    }
+
+    /// Source of every shader that changed since the last call. All of them count as changed
+    /// the first time.
+    pub fn poll(&mut self) -> Vec<(RenderMode, io::Result<String>)> {
+        let mut changed = Vec::new();
+        for (mode, loaded) in &mut self.loaded {
+            let path = self.directory.join(mode.shader_file());
+            let modified = match fs::metadata(&path).and_then(|metadata| metadata.modified()) {
+                Ok(modified) => modified,
+                Err(error) => {
+                    // Only report a missing file once, not every frame
+                    if loaded.is_none() {
+                        *loaded = Some(SystemTime::UNIX_EPOCH);
+                        changed.push((*mode, Err(error)));
+                    }
+                    continue;
+                }
+            };
+            if *loaded != Some(modified) {
+                *loaded = Some(modified);
+                changed.push((*mode, fs::read_to_string(&path)));
+            }
+        }
+        changed
+    }
+}
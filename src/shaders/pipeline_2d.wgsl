@@ -2,6 +2,61 @@
 var sim_texture: texture_2d<f32>;
 @group(0) @binding(1)
 var sim_sampler: sampler;
+@group(0) @binding(2)
+var previous_sim_texture: texture_2d<f32>;
+
+struct Interpolation {
+    // 0 draws the previous tick, 1 the current one
+    alpha: f32,
+}
+@group(0) @binding(3)
+var<uniform> interpolation: Interpolation;
+
+fn sample_sim(uv: vec2<f32>) -> vec4<f32> {
+    let previous = textureSample(previous_sim_texture, sim_sampler, uv);
+    return mix(previous, textureSample(sim_texture, sim_sampler, uv), interpolation.alpha);
+}
+
+// See palette::ColorMap
+@group(1) @binding(0)
+var palette: texture_1d<f32>;
+@group(1) @binding(1)
+var palette_sampler: sampler;
+
+struct ColorMap {
+    // 0 height, 1 velocity, 2 medium, 3 energy, see palette::ColorField
+    field: u32,
+    range: f32,
+}
+@group(1) @binding(2)
+var<uniform> color_map: ColorMap;
+
+const wall_color = vec3<f32>(0.02, 0.02, 0.02);
+
+// Where the cell lands on the palette, 0 at the bottom and 1 at the top
+fn palette_position(cell: vec4<f32>) -> f32 {
+    let range = max(color_map.range, 1e-6);
+    switch color_map.field {
+        case 1u: {
+            return 0.5 + 0.5 * cell.g / range;
+        }
+        case 2u: {
+            return cell.b / range;
+        }
+        case 3u: {
+            return (cell.r * cell.r + cell.g * cell.g) / range;
+        }
+        default: {
+            return 0.5 + 0.5 * cell.r / range;
+        }
+    }
+}
+
+fn cell_color(cell: vec4<f32>) -> vec3<f32> {
+    // Sampled before picking the wall colour, textureSample has to run for every fragment
+    let color = textureSample(palette, palette_sampler, clamp(palette_position(cell), 0.0, 1.0)).rgb;
+    return select(color, wall_color, cell.b < 0.0);
+}
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
@@ -27,6 +82,5 @@ fn vs_main(
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-     var color = textureSample(sim_texture, sim_sampler, in.uv);
-     return vec4<f32>(color.r, color.g, 0.0, 1.0);
+     return vec4<f32>(cell_color(sample_sim(in.uv)), 1.0);
 }
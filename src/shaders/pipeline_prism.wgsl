@@ -1,13 +1,128 @@
 struct CameraUniform {
     view_proj: mat4x4<f32>,
+    // World space, w is 1
+    position: vec4<f32>,
 }
 @group(0) @binding(0) // 1.
 var<uniform> camera: CameraUniform;
 
+// See lighting::LightingUniform
+struct Light {
+    // Direction the light shines in with w = 0, or the position of a point light with w = 1
+    vector: vec4<f32>,
+    // Colour times intensity
+    color: vec4<f32>,
+}
+
+struct Lighting {
+    // World space to the shadow map
+    shadow_view_proj: mat4x4<f32>,
+    ambient: vec3<f32>,
+    light_count: u32,
+    specular: f32,
+    shininess: f32,
+    // Distance at which point lights are down to half strength
+    falloff: f32,
+    // Index of the light casting shadows, light_count or more for none
+    shadow_light: u32,
+    lights: array<Light, 4>,
+}
+@group(0) @binding(1)
+var<uniform> lighting: Lighting;
+
 @group(1) @binding(0)
 var sim_texture: texture_2d<f32>;
 @group(1) @binding(1)
 var sim_sampler: sampler;
+@group(1) @binding(2)
+var previous_sim_texture: texture_2d<f32>;
+
+struct Interpolation {
+    // 0 draws the previous tick, 1 the current one
+    alpha: f32,
+}
+@group(1) @binding(3)
+var<uniform> interpolation: Interpolation;
+
+fn load_sim(coord: vec2<u32>) -> vec4<f32> {
+    let previous = textureLoad(previous_sim_texture, coord, 0);
+    return mix(previous, textureLoad(sim_texture, coord, 0), interpolation.alpha);
+}
+
+fn sample_sim(uv: vec2<f32>) -> vec4<f32> {
+    let previous = textureSample(previous_sim_texture, sim_sampler, uv);
+    return mix(previous, textureSample(sim_texture, sim_sampler, uv), interpolation.alpha);
+}
+
+// See palette::ColorMap
+@group(2) @binding(0)
+var palette: texture_1d<f32>;
+@group(2) @binding(1)
+var palette_sampler: sampler;
+
+struct ColorMap {
+    // 0 height, 1 velocity, 2 medium, 3 energy, see palette::ColorField
+    field: u32,
+    range: f32,
+}
+@group(2) @binding(2)
+var<uniform> color_map: ColorMap;
+
+// Depth of the prisms as seen from the shadow light, see vs_shadow
+@group(3) @binding(0)
+var shadow_map: texture_depth_2d;
+@group(3) @binding(1)
+var shadow_sampler: sampler_comparison;
+
+// How much of the shadow light reaches world_pos, 0 in full shadow. Averages a 3x3 block of
+// comparisons so the shadow edges come out soft instead of blocky.
+fn shadow_factor(world_pos: vec3<f32>) -> f32 {
+    if (lighting.shadow_light >= lighting.light_count) {
+        return 1.0;
+    }
+    let shadow_pos = lighting.shadow_view_proj * vec4<f32>(world_pos, 1.0);
+    let ndc = shadow_pos.xyz / shadow_pos.w;
+    let uv = vec2<f32>(ndc.x * 0.5 + 0.5, -ndc.y * 0.5 + 0.5);
+    if (any(uv < vec2<f32>(0.0)) || any(uv > vec2<f32>(1.0)) || ndc.z > 1.0) {
+        return 1.0;
+    }
+    let texel = 1.0 / vec2<f32>(textureDimensions(shadow_map));
+    var lit = 0.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let offset = vec2<f32>(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(shadow_map, shadow_sampler, uv + offset, ndc.z);
+        }
+    }
+    return lit / 9.0;
+}
+
+const wall_color = vec3<f32>(0.02, 0.02, 0.02);
+
+// Where the cell lands on the palette, 0 at the bottom and 1 at the top
+fn palette_position(cell: vec4<f32>) -> f32 {
+    let range = max(color_map.range, 1e-6);
+    switch color_map.field {
+        case 1u: {
+            return 0.5 + 0.5 * cell.g / range;
+        }
+        case 2u: {
+            return cell.b / range;
+        }
+        case 3u: {
+            return (cell.r * cell.r + cell.g * cell.g) / range;
+        }
+        default: {
+            return 0.5 + 0.5 * cell.r / range;
+        }
+    }
+}
+
+fn cell_color(cell: vec4<f32>) -> vec3<f32> {
+    // Sampled before picking the wall colour, textureSample has to run for every fragment
+    let color = textureSample(palette, palette_sampler, clamp(palette_position(cell), 0.0, 1.0)).rgb;
+    return select(color, wall_color, cell.b < 0.0);
+}
 
 struct VertexInput {
     @location(0) vertex: vec3<f32>,
@@ -24,43 +139,70 @@ struct VertexOutput {
     @location(3) world_normal: vec3<f32>,
 }
 
-@vertex
-fn vs_main(
-    model: VertexInput,
-) -> VertexOutput {
-    var sim_cell = textureLoad(sim_texture, model.sim_coord, 0);
+// Where the vertex ends up once the waves have moved the prism
+fn world_position(model: VertexInput) -> vec3<f32> {
+    var sim_cell = load_sim(model.sim_coord);
     var position = model.vertex;
     position.x += model.position.x;
     position.z += model.position.y;
     position.y += sim_cell.r * 15.0;
+    return position;
+}
 
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    let position = world_position(model);
 
     var out: VertexOutput;
     out.color = model.vertex;
     out.world_pos = position;
     out.world_normal = model.normal;
-    out.tex_coords= vec2<f32>(f32(model.sim_coord.x) / 128.0, f32(model.sim_coord.y) / 128.0);
+    out.tex_coords = vec2<f32>(model.sim_coord) / vec2<f32>(textureDimensions(sim_texture));
     out.clip_position = camera.view_proj * vec4<f32>(position, 1.0);
     return out;
 }
 
-// Fragment shader
-const light_pos = vec3<f32>(0.0, 2.0, 0.0);
-const light_color = vec3<f32>(1.0, 1.0, 1.0);
+// Depth only, drawn from the shadow light into the shadow map
+@vertex
+fn vs_shadow(
+    model: VertexInput,
+) -> @builtin(position) vec4<f32> {
+    return lighting.shadow_view_proj * vec4<f32>(world_position(model), 1.0);
+}
 
+// Fragment shader
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let ambient_strength = 0.1;
-    let ambient_color = light_color * ambient_strength;
-
-    let light_dir = normalize(light_pos - in.world_pos);
-    let diffuse_strength = max(dot(in.world_normal, light_dir), 0.0);
-    let diffuse_color = light_color * diffuse_strength;
+    let sim_color = cell_color(sample_sim(in.tex_coords));
+    let normal = normalize(in.world_normal);
+    let view_dir = normalize(camera.position.xyz - in.world_pos);
 
-    var tex_color = textureSample(sim_texture, sim_sampler, in.tex_coords);
-    var sim_color = vec3<f32>(tex_color.r * 2.0, 0.0, tex_color.r * 2.0);
-    sim_color += 0.001;
+    var diffuse = lighting.ambient;
+    var specular = vec3<f32>(0.0);
+    let shadow = shadow_factor(in.world_pos);
+    for (var i = 0u; i < lighting.light_count; i++) {
+        let light = lighting.lights[i];
+        var light_dir = normalize(-light.vector.xyz);
+        var radiance = light.color.rgb;
+        if (light.vector.w > 0.5) {
+            let offset = light.vector.xyz - in.world_pos;
+            let light_distance = length(offset);
+            light_dir = offset / max(light_distance, 1e-6);
+            let scaled = light_distance / max(lighting.falloff, 1e-6);
+            radiance /= 1.0 + scaled * scaled;
+        }
+        if (i == lighting.shadow_light) {
+            radiance *= shadow;
+        }
+        let facing = dot(normal, light_dir);
+        diffuse += radiance * max(facing, 0.0);
+        // Blinn-Phong, skipped on faces turned away from the light
+        let half_dir = normalize(light_dir + view_dir);
+        let highlight = pow(max(dot(normal, half_dir), 0.0), lighting.shininess);
+        specular += radiance * lighting.specular * highlight * select(0.0, 1.0, facing > 0.0);
+    }
 
-    let result = (ambient_color + diffuse_color) * sim_color;
-    return vec4<f32>(result, 1.0);
+    return vec4<f32>(diffuse * sim_color + specular, 1.0);
 }
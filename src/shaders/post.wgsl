@@ -0,0 +1,76 @@
+// Full screen passes over an image that's already been drawn, see sim_renderer::PipelinePost
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+
+// Furthest along an edge to blur, in pixels
+const span_max = 8.0;
+// Keeps the blur from spreading over faint gradients
+const reduce_mul = 1.0 / 8.0;
+const reduce_min = 1.0 / 128.0;
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+}
+
+// One triangle big enough to cover the screen
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> VertexOutput {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    var out: VertexOutput;
+    out.uv = uv;
+    out.clip_position = vec4<f32>(uv.x * 2.0 - 1.0, 1.0 - uv.y * 2.0, 0.0, 1.0);
+    return out;
+}
+
+fn sample_source(uv: vec2<f32>) -> vec3<f32> {
+    return textureSampleLevel(source, source_sampler, uv, 0.0).rgb;
+}
+
+// Roughly perceptual brightness, the source holds linear colour
+fn luma(color: vec3<f32>) -> f32 {
+    return sqrt(dot(color, vec3<f32>(0.299, 0.587, 0.114)));
+}
+
+// Copies the image as it is, e.g. into a format that can be read back
+@fragment
+fn fs_copy(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(sample_source(in.uv), 1.0);
+}
+
+// Smooths the edges of the scene as drawn by the prism pipeline, see Antialiasing::Fxaa
+@fragment
+fn fs_fxaa(in: VertexOutput) -> @location(0) vec4<f32> {
+    let texel = 1.0 / vec2<f32>(textureDimensions(source));
+    let luma_nw = luma(sample_source(in.uv + vec2<f32>(-1.0, -1.0) * texel));
+    let luma_ne = luma(sample_source(in.uv + vec2<f32>(1.0, -1.0) * texel));
+    let luma_sw = luma(sample_source(in.uv + vec2<f32>(-1.0, 1.0) * texel));
+    let luma_se = luma(sample_source(in.uv + vec2<f32>(1.0, 1.0) * texel));
+    let luma_m = luma(sample_source(in.uv));
+    let luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    let luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // Points along the edge, at right angles to the steepest change in brightness
+    var dir = vec2<f32>(
+        (luma_sw + luma_se) - (luma_nw + luma_ne),
+        (luma_nw + luma_sw) - (luma_ne + luma_se),
+    );
+    let reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * reduce_mul, reduce_min);
+    let scale = 1.0 / (min(abs(dir.x), abs(dir.y)) + reduce);
+    dir = clamp(dir * scale, vec2<f32>(-span_max), vec2<f32>(span_max)) * texel;
+
+    let color_a = 0.5 * (
+        sample_source(in.uv + dir * (1.0 / 3.0 - 0.5)) +
+        sample_source(in.uv + dir * (2.0 / 3.0 - 0.5))
+    );
+    let color_b = color_a * 0.5 + 0.25 * (
+        sample_source(in.uv - dir * 0.5) +
+        sample_source(in.uv + dir * 0.5)
+    );
+    // The wider blur overshot the neighbourhood, it crossed more than one edge
+    let luma_b = luma(color_b);
+    let color = select(color_b, color_a, luma_b < luma_min || luma_b > luma_max);
+    return vec4<f32>(color, 1.0);
+}
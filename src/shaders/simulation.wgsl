@@ -0,0 +1,189 @@
+// Compute version of WaveSimulation::advance, see simulation.rs for the reference implementation.
+// Channels match WavePoint: r = value, g = velocity, b = medium, a = extra
+
+const MAX_POKES: u32 = 32u;
+const MAX_SOURCES: u32 = 16u;
+const SPONGE_STRENGTH: f32 = 0.15;
+
+// Matches boundary::EdgeMode
+const EDGE_FIXED: u32 = 0u;
+const EDGE_FREE: u32 = 1u;
+const EDGE_PERIODIC: u32 = 2u;
+const EDGE_ABSORBING: u32 = 3u;
+const INSIDE: u32 = 4u;
+
+// Matches poke::PokeShape
+const POKE_BOX: u32 = 0u;
+const POKE_GAUSSIAN: u32 = 1u;
+const POKE_COSINE: u32 = 2u;
+const POKE_RING: u32 = 3u;
+const PI: f32 = 3.14159265;
+
+struct Poke {
+    // (center.x, center.y, radius, amplitude), all in cells
+    area: vec4<f32>,
+    // (shape, unused, unused, unused)
+    shape: vec4<u32>,
+}
+
+struct SimParams {
+    damping: f32,
+    poke_count: u32,
+    sponge_thickness: u32,
+    source_count: u32,
+    // Edge modes in left, right, top, bottom order
+    edges: vec4<u32>,
+    pokes: array<Poke, MAX_POKES>,
+    // Segment of each source in cells: (start.x, start.y, end.x, end.y)
+    source_segments: array<vec4<f32>, MAX_SOURCES>,
+    // (value, radius, unused, unused)
+    source_values: array<vec4<f32>, MAX_SOURCES>,
+}
+
+@group(0) @binding(0)
+var previous: texture_2d<f32>;
+@group(0) @binding(1)
+var current: texture_storage_2d<rgba32float, write>;
+@group(0) @binding(2)
+var<uniform> params: SimParams;
+
+fn get_value(coord: vec2<i32>) -> f32 {
+    return textureLoad(previous, coord, 0).r;
+}
+
+// See Boundaries::neighbour
+fn neighbour_value(coord: vec2<i32>, offset: vec2<i32>, size: vec2<i32>) -> f32 {
+    var neighbour = coord + offset;
+    var mode = INSIDE;
+    if (neighbour.x < 0) {
+        mode = params.edges.x;
+    } else if (neighbour.x >= size.x) {
+        mode = params.edges.y;
+    } else if (neighbour.y < 0) {
+        mode = params.edges.z;
+    } else if (neighbour.y >= size.y) {
+        mode = params.edges.w;
+    }
+    switch mode {
+        case EDGE_FIXED: {
+            return 0.0;
+        }
+        case EDGE_FREE, EDGE_ABSORBING: {
+            neighbour = coord;
+        }
+        case EDGE_PERIODIC: {
+            neighbour = (neighbour + size) % size;
+        }
+        default: {}
+    }
+    return get_value(neighbour);
+}
+
+// See Boundaries::absorption
+fn absorption(coord: vec2<i32>, size: vec2<i32>) -> f32 {
+    let thickness = i32(params.sponge_thickness);
+    if (thickness == 0) {
+        return 1.0;
+    }
+    let distances = vec4<i32>(coord.x, size.x - 1 - coord.x, coord.y, size.y - 1 - coord.y);
+    var factor = 1.0;
+    for (var i = 0; i < 4; i++) {
+        if (params.edges[i] == EDGE_ABSORBING && distances[i] < thickness) {
+            let depth = f32(thickness - distances[i]) / f32(thickness);
+            factor = min(factor, 1.0 - SPONGE_STRENGTH * depth * depth);
+        }
+    }
+    return factor;
+}
+
+// See Source::covers
+fn source_covers(index: u32, point: vec2<f32>) -> bool {
+    let start = params.source_segments[index].xy;
+    let segment = params.source_segments[index].zw - start;
+    let length_squared = dot(segment, segment);
+    var t = 0.0;
+    if (length_squared > 0.0) {
+        t = clamp(dot(point - start, segment) / length_squared, 0.0, 1.0);
+    }
+    return distance(point, start + segment * t) <= params.source_values[index].y;
+}
+
+@compute @workgroup_size(8, 8)
+fn advance(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(previous);
+    if (id.x >= size.x || id.y >= size.y) {
+        return;
+    }
+    let coord = vec2<i32>(id.xy);
+    var cell = textureLoad(previous, coord, 0);
+    if (cell.b >= 0.0) {
+        let bounds = vec2<i32>(size);
+        let mid = (neighbour_value(coord, vec2<i32>(-1, 0), bounds)
+            + neighbour_value(coord, vec2<i32>(1, 0), bounds)
+            + neighbour_value(coord, vec2<i32>(0, -1), bounds)
+            + neighbour_value(coord, vec2<i32>(0, 1), bounds)) / 4.0;
+
+        let propagation = cell.b * 1.5;
+        let absorb = absorption(coord, bounds);
+        let new_vel = (propagation * (mid - cell.r) + cell.g * params.damping) * absorb;
+        let previous_value = cell.r;
+        cell.r = (cell.r + new_vel) * absorb;
+        cell.g = new_vel;
+
+        // See WaveSimulation::apply_sources
+        let center = vec2<f32>(id.xy) + 0.5;
+        for (var i = 0u; i < params.source_count; i++) {
+            if (source_covers(i, center)) {
+                let value = params.source_values[i].x;
+                cell.r = value;
+                cell.g = value - previous_value;
+            }
+        }
+    } else {
+        cell.r = 0.0;
+        cell.g = 0.0;
+    }
+    textureStore(current, coord, cell);
+}
+
+// See PokeShape::weight
+fn poke_weight(shape: u32, distance: f32) -> f32 {
+    if (distance > 1.0) {
+        return 0.0;
+    }
+    switch shape {
+        case POKE_GAUSSIAN: {
+            return exp(-4.5 * distance * distance);
+        }
+        case POKE_COSINE: {
+            return 0.5 + 0.5 * cos(PI * distance);
+        }
+        case POKE_RING: {
+            let offset = (distance - 0.7) / 0.3;
+            if (abs(offset) >= 1.0) {
+                return 0.0;
+            }
+            return 0.5 + 0.5 * cos(PI * offset);
+        }
+        case POKE_BOX, default: {
+            return 1.0;
+        }
+    }
+}
+
+@compute @workgroup_size(8, 8)
+fn apply_pokes(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(previous);
+    if (id.x >= size.x || id.y >= size.y) {
+        return;
+    }
+    let coord = vec2<i32>(id.xy);
+    var cell = textureLoad(previous, coord, 0);
+    let center = vec2<f32>(id.xy) + 0.5;
+    for (var i = 0u; i < params.poke_count; i++) {
+        let area = params.pokes[i].area;
+        let relative_distance = distance(center, area.xy) / max(area.z, 1e-6);
+        cell.r += area.w * poke_weight(params.pokes[i].shape.x, relative_distance);
+    }
+    textureStore(current, coord, cell);
+}
@@ -1,47 +1,194 @@
-use macaw::Mat4;
+use std::ops::RangeInclusive;
+use macaw::{Mat4, UVec2, Vec2, Vec3};
+use serde::{Deserialize, Serialize};
 use wgpu::util::DeviceExt;
+use crate::lighting::Lighting;
 use crate::mesh::{Mesh, UploadedMesh};
 use crate::mesh_grid::{MeshGrid, UploadedMeshGrid};
+use crate::palette::{self, ColorField, ColorMap, Palette};
+use crate::render_target::{OffscreenTarget, SceneTextures};
 use crate::{mesh, mesh_grid, texture};
 
 const CLEAR_COLOR: f64 = 0.0001;
 
+/// Width and height of the shadow map the main directional light draws into
+const SHADOW_MAP_SIZE: u32 = 2048;
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub enum RenderMode {
     Texture,
     Prism,
 }
 
+impl RenderMode {
+    pub const ALL: [RenderMode; 2] = [RenderMode::Texture, RenderMode::Prism];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RenderMode::Texture => "texture",
+            RenderMode::Prism => "prism",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name().eq_ignore_ascii_case(name))
+    }
+
+    /// The shader in `src/shaders` the mode draws with
+    pub fn shader_file(&self) -> &'static str {
+        match self {
+            RenderMode::Texture => "pipeline_2d.wgsl",
+            RenderMode::Prism => "pipeline_prism.wgsl",
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum PrismType {
+    Square,
+    Hex,
+}
+
+impl PrismType {
+    pub const ALL: [PrismType; 2] = [PrismType::Square, PrismType::Hex];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PrismType::Square => "Square",
+            PrismType::Hex => "Hexagon",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|prism| prism.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// How the prism edges are smoothed. The flat view is a single quad and is drawn as it is.
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Antialiasing {
+    Off,
+    Msaa2,
+    Msaa4,
+    Msaa8,
+    /// Blurs along edges after drawing, for adapters that can't multisample
+    Fxaa,
+}
+
+impl Antialiasing {
+    pub const ALL: [Antialiasing; 5] = [
+        Antialiasing::Off,
+        Antialiasing::Msaa2,
+        Antialiasing::Msaa4,
+        Antialiasing::Msaa8,
+        Antialiasing::Fxaa,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Antialiasing::Off => "Off",
+            Antialiasing::Msaa2 => "2x MSAA",
+            Antialiasing::Msaa4 => "4x MSAA",
+            Antialiasing::Msaa8 => "8x MSAA",
+            Antialiasing::Fxaa => "FXAA",
+        }
+    }
+
+    /// Samples per pixel the prisms are drawn with
+    pub fn sample_count(&self) -> u32 {
+        match self {
+            Antialiasing::Off | Antialiasing::Fxaa => 1,
+            Antialiasing::Msaa2 => 2,
+            Antialiasing::Msaa4 => 4,
+            Antialiasing::Msaa8 => 8,
+        }
+    }
+}
+
+/// Shape and layout of the prisms in the 3D view
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RenderConfig {
+    pub prism_type: PrismType,
+    pub prism_height: f32,
+    pub grid_size: usize,
+    pub step_size: f32,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        Self {
+            prism_type: PrismType::Hex,
+            prism_height: 5.0,
+            grid_size: 16,
+            step_size: 1.0,
+        }
+    }
+}
+
+impl RenderConfig {
+    /// What the settings window and `--grid-size` go between
+    pub const GRID_SIZES: RangeInclusive<usize> = 2..=148;
+    pub const STEP_SIZES: RangeInclusive<f32> = 1.0..=5.0;
+    pub const PRISM_HEIGHTS: RangeInclusive<f32> = 1.0..=256.0;
+
+    /// The prism mesh and the grid of instances covering a simulation of `sim_size`
+    pub fn build(&self, sim_size: UVec2) -> (Mesh, MeshGrid) {
+        match self.prism_type {
+            PrismType::Square => {
+                let mesh = mesh::square_prism(self.prism_height);
+                let grid = MeshGrid::square_grid(self.grid_size, self.step_size, sim_size);
+                (mesh, grid)
+            }
+            PrismType::Hex => {
+                let mesh = mesh::hex_prism(self.prism_height);
+                let grid = MeshGrid::hex_grid(self.grid_size, self.step_size, sim_size);
+                (mesh, grid)
+            }
+        }
+    }
+}
+
 pub struct SimRenderer {
     prism: UploadedMesh,
     grid: UploadedMeshGrid,
     sim_data: SimTextureData,
     pipeline_prism: PipelinePrism,
     pipeline_2d: Pipeline2D,
+    pipeline_fxaa: PipelinePost,
+    /// Copies captures into `OffscreenTarget::DEFAULT_FORMAT`, see `copy_to_readable`
+    pipeline_copy: PipelinePost,
+    /// Indexed by `RenderMode`
+    color_maps: [ColorMapData; 2],
     pub(crate) render_mode: RenderMode,
 }
 
 impl SimRenderer {
     pub fn new(
         device: &wgpu::Device,
-        surface_config: &wgpu::SurfaceConfiguration,
+        queue: &wgpu::Queue,
+        format: wgpu::TextureFormat,
         prism: &Mesh,
         grid: &MeshGrid,
-        sim_divisions: u32,
+        sim_size: UVec2,
     ) -> Self {
         let prism = prism.push_to_device(device);
         let grid = grid.push_to_device(device);
-        let sim_data = SimTextureData::new(device, sim_divisions);
+        let sim_data = SimTextureData::new(device, sim_size);
+        let color_map_layout = ColorMapData::layout(device);
 
         let pipeline_prism = PipelinePrism::new(
             device,
-            surface_config,
+            format,
             &sim_data.bind_group_layout,
+            &color_map_layout,
         );
 
         let pipeline_2d = Pipeline2D::new(
             device,
-            surface_config,
+            format,
             &sim_data.bind_group_layout,
+            &color_map_layout,
         );
 
         Self {
@@ -50,15 +197,42 @@ impl SimRenderer {
             sim_data,
             pipeline_prism,
             pipeline_2d,
+            pipeline_fxaa: PipelinePost::new(device, format, "fs_fxaa"),
+            pipeline_copy: PipelinePost::new(device, OffscreenTarget::DEFAULT_FORMAT, "fs_copy"),
+            color_maps: RenderMode::ALL.map(|mode| ColorMapData::new(device, queue, &color_map_layout, mode)),
             render_mode: RenderMode::Prism,
         }
     }
 
-    pub fn set_camera_transform(&self, queue: &wgpu::Queue, transform: Mat4) {
+    /// Colours the field drawn in `mode` with `palette`, see `palette::ColorMap`
+    pub fn set_color_map(
+        &self,
+        queue: &wgpu::Queue,
+        mode: RenderMode,
+        palette: &Palette,
+        field: ColorField,
+        range: f32,
+    ) {
+        self.color_maps[mode as usize].write(queue, palette, field, range);
+    }
+
+    /// `position` is where the camera is in world space, for the specular highlights
+    pub fn set_camera_transform(&self, queue: &wgpu::Queue, transform: Mat4, position: Vec3) {
         queue.write_buffer(
             &self.pipeline_prism.camera_buffer,
             0,
-            bytemuck::cast_slice(&transform.to_cols_array()))
+            bytemuck::cast_slice(&camera_uniform(transform, position)))
+    }
+
+    /// Lights the prisms of a grid of `grid_extent`, see `MeshGrid::extent`. The shadow pass only
+    /// runs while a light casts shadows.
+    pub fn set_lighting(&mut self, queue: &wgpu::Queue, lighting: &Lighting, grid_extent: Vec2, prism_height: f32) {
+        let uniform = lighting.uniform(grid_extent, prism_height);
+        self.pipeline_prism.casts_shadows = uniform.casts_shadows();
+        queue.write_buffer(
+            &self.pipeline_prism.lighting_buffer,
+            0,
+            bytemuck::bytes_of(&uniform))
     }
 
     pub fn update_prism(&mut self, device: &wgpu::Device, prism: &Mesh) {
@@ -69,39 +243,127 @@ impl SimRenderer {
         self.grid = grid.push_to_device(device);
     }
 
-    pub fn update_sim_data(&self, queue: &wgpu::Queue, divisions: u32, data: &[u8]) {
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                texture: &self.sim_data.texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-                aspect: wgpu::TextureAspect::All,
-            },
-            &data,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * 4 * divisions),
-                rows_per_image: Some(divisions),
-            },
-            self.sim_data.texture_size,
-        );
+    /// Size of the grid the sim texture was built for
+    pub fn sim_size(&self) -> UVec2 {
+        UVec2::new(self.sim_data.texture_size.width, self.sim_data.texture_size.height)
     }
 
-    pub fn resize(
-        &mut self,
+    /// Rebuilds the sim texture for a grid of a different size
+    pub fn resize_sim(&mut self, device: &wgpu::Device, size: UVec2) {
+        self.sim_data.resize(device, size);
+    }
+
+    /// Uploads the latest two states of a CPU solver, see `set_interpolation`
+    pub fn update_sim_data(&self, queue: &wgpu::Queue, size: UVec2, current: &[u8], previous: &[u8]) {
+        for (texture, data) in [(&self.sim_data.texture, current), (&self.sim_data.previous_texture, previous)] {
+            queue.write_texture(
+                wgpu::ImageCopyTexture {
+                    texture,
+                    mip_level: 0,
+                    origin: wgpu::Origin3d::ZERO,
+                    aspect: wgpu::TextureAspect::All,
+                },
+                data,
+                wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(4 * 4 * size.x),
+                    rows_per_image: Some(size.y),
+                },
+                self.sim_data.texture_size,
+            );
+        }
+    }
+
+    /// Feeds the renderer straight from the textures the GPU simulation wrote to
+    pub fn copy_sim_texture(
+        &self,
         device: &wgpu::Device,
-        surface_config: &wgpu::SurfaceConfiguration,
+        queue: &wgpu::Queue,
+        current: &wgpu::Texture,
+        previous: &wgpu::Texture,
     ) {
-        self.pipeline_prism.depth_texture = texture::Texture::create_depth_texture(
-            device,
-            surface_config,
-            "depth texture",
+        let mut encoder = device.create_command_encoder(
+            &wgpu::CommandEncoderDescriptor { label: Some("sim texture copy encoder") }
+        );
+        for (source, destination) in [(current, &self.sim_data.texture), (previous, &self.sim_data.previous_texture)] {
+            encoder.copy_texture_to_texture(
+                source.as_image_copy(),
+                destination.as_image_copy(),
+                self.sim_data.texture_size,
+            );
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    /// Where to draw the field between the previous (0.0) and current (1.0) state
+    pub fn set_interpolation(&self, queue: &wgpu::Queue, alpha: f32) {
+        queue.write_buffer(
+            &self.sim_data.interpolation_buffer,
+            0,
+            bytemuck::cast_slice(&[alpha, 0.0, 0.0, 0.0]),
         )
     }
 
+    /// Rebuilds the pipeline for `mode` from WGSL source. Keeps the old pipeline and returns the
+    /// validation error if the source doesn't compile.
+    pub fn reload_shader(&mut self, device: &wgpu::Device, mode: RenderMode, source: &str) -> Result<(), String> {
+        let label = mode.shader_file();
+        match mode {
+            RenderMode::Texture => {
+                let pipeline = &mut self.pipeline_2d;
+                pipeline.pipeline = compile(device, label, source, |shader| {
+                    Pipeline2D::create_pipeline(device, &pipeline.layout, pipeline.format, &shader)
+                })?;
+            }
+            RenderMode::Prism => {
+                let pipeline = &mut self.pipeline_prism;
+                let (format, sample_count) = (pipeline.format, pipeline.sample_count);
+                (pipeline.pipeline, pipeline.shadow_pipeline, pipeline.shader) = compile(device, label, source, |shader| (
+                    PipelinePrism::create_pipeline(device, &pipeline.layout, format, sample_count, &shader),
+                    PipelinePrism::create_shadow_pipeline(device, &pipeline.shadow_layout, &shader),
+                    // Kept for rebuilding the pipeline when the antialiasing changes
+                    shader,
+                ))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies `source`, drawn by `render`, into `view` in `OffscreenTarget::DEFAULT_FORMAT` so it
+    /// can be read back whatever format the window uses
+    pub fn copy_to_readable(
+        &self,
+        device: &wgpu::Device,
+        source: &wgpu::TextureView,
+        view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        self.pipeline_copy.render(device, source, view, encoder);
+    }
+
+    /// Rebuilds the prism pipeline to draw into targets with `sample_count` samples per pixel, see
+    /// `SceneTextures`
+    pub fn set_sample_count(&mut self, device: &wgpu::Device, sample_count: u32) {
+        let pipeline = &mut self.pipeline_prism;
+        if pipeline.sample_count != sample_count {
+            pipeline.sample_count = sample_count;
+            pipeline.pipeline = PipelinePrism::create_pipeline(
+                device,
+                &pipeline.layout,
+                pipeline.format,
+                sample_count,
+                &pipeline.shader,
+            );
+        }
+    }
+
+    /// Draws the field into `view`. `scene` has to be the same size and match the sample count
+    /// set with `set_sample_count`, see `RenderTarget`.
     pub fn render(
         &mut self,
+        device: &wgpu::Device,
         view: &wgpu::TextureView,
+        scene: &SceneTextures,
         encoder: &mut wgpu::CommandEncoder,
     ) {
         match self.render_mode {
@@ -110,61 +372,203 @@ impl SimRenderer {
                     view,
                     encoder,
                     &self.sim_data.bind_group,
+                    &self.color_maps[RenderMode::Texture as usize].bind_group,
                 )
             }
             RenderMode::Prism => {
+                // MSAA resolves into the view at the end of the pass, FXAA reads the scene back
+                // and writes the smoothed image to it
+                let (color_view, resolve_target) = match (scene.antialiasing, &scene.color) {
+                    (Antialiasing::Fxaa, Some(color)) => (color, None),
+                    (_, Some(color)) => (color, Some(view)),
+                    (_, None) => (view, None),
+                };
                 self.pipeline_prism.render(
-                    view,
+                    [color_view, &scene.depth.view],
+                    resolve_target,
                     encoder,
-                    &self.sim_data.bind_group,
+                    [&self.sim_data.bind_group, &self.color_maps[RenderMode::Prism as usize].bind_group],
                     &self.prism,
                     &self.grid,
                 );
+                if let (Antialiasing::Fxaa, Some(color)) = (scene.antialiasing, &scene.color) {
+                    self.pipeline_fxaa.render(device, color, view, encoder);
+                }
             }
         }
     }
 }
 
+/// The two latest states of the simulation. Shaders blend between them so the field moves
+/// smoothly when frames land between ticks.
 struct SimTextureData {
     bind_group_layout: wgpu::BindGroupLayout,
     bind_group: wgpu::BindGroup,
     texture_size: wgpu::Extent3d,
     texture: wgpu::Texture,
+    previous_texture: wgpu::Texture,
+    interpolation_buffer: wgpu::Buffer,
 }
 
 impl SimTextureData {
-    pub fn new(device: &wgpu::Device, divisions: u32) -> Self {
-        let texture_size = wgpu::Extent3d {
-            width: divisions,
-            height: divisions,
-            depth_or_array_layers: 1,
+    pub fn new(device: &wgpu::Device, size: UVec2) -> Self {
+        let sim_texture_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            },
+            count: None,
         };
-        let sim_texture = device.create_texture(
-            &wgpu::TextureDescriptor {
-                size: texture_size,
-                mip_level_count: 1,
-                sample_count: 1,
-                dimension: wgpu::TextureDimension::D2,
-                format: wgpu::TextureFormat::Rgba32Float,
-                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-                label: Some("sim_texture"),
-                view_formats: &[],
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                sim_texture_entry(0),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                sim_texture_entry(2),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("sim_texture_bind_group_layout"),
+        });
+        let interpolation_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("sim interpolation"),
+                contents: bytemuck::cast_slice(&[1.0f32, 0.0, 0.0, 0.0]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             }
         );
+        let (texture_size, texture, previous_texture, bind_group) =
+            Self::create_textures(device, &bind_group_layout, &interpolation_buffer, size);
+        Self {
+            bind_group_layout,
+            bind_group,
+            texture_size,
+            texture,
+            previous_texture,
+            interpolation_buffer,
+        }
+    }
+
+    pub fn resize(&mut self, device: &wgpu::Device, size: UVec2) {
+        let (texture_size, texture, previous_texture, bind_group) =
+            Self::create_textures(device, &self.bind_group_layout, &self.interpolation_buffer, size);
+        self.texture_size = texture_size;
+        self.texture = texture;
+        self.previous_texture = previous_texture;
+        self.bind_group = bind_group;
+    }
+
+    fn create_textures(
+        device: &wgpu::Device,
+        bind_group_layout: &wgpu::BindGroupLayout,
+        interpolation_buffer: &wgpu::Buffer,
+        size: UVec2,
+    ) -> (wgpu::Extent3d, wgpu::Texture, wgpu::Texture, wgpu::BindGroup) {
+        let texture_size = wgpu::Extent3d {
+            width: size.x,
+            height: size.y,
+            depth_or_array_layers: 1,
+        };
+        let [sim_texture, previous_texture] = ["sim_texture", "previous_sim_texture"].map(|label| {
+            device.create_texture(
+                &wgpu::TextureDescriptor {
+                    size: texture_size,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: wgpu::TextureDimension::D2,
+                    format: wgpu::TextureFormat::Rgba32Float,
+                    usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                    label: Some(label),
+                    view_formats: &[],
+                }
+            )
+        });
         let sim_texture_view = sim_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let previous_texture_view = previous_texture.create_view(&wgpu::TextureViewDescriptor::default());
         let sim_texture_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             mag_filter: wgpu::FilterMode::Linear,
             min_filter: wgpu::FilterMode::Linear,
             ..Default::default()
         });
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        let bind_group = device.create_bind_group(
+            &wgpu::BindGroupDescriptor {
+                layout: bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&sim_texture_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::Sampler(&sim_texture_sampler),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&previous_texture_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: interpolation_buffer.as_entire_binding(),
+                    },
+                ],
+                label: Some("sim_texture_bind_group"),
+            },
+        );
+        (texture_size, sim_texture, previous_texture, bind_group)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct ColorMapUniform {
+    /// `ColorField` as an index, matching `palette_position` in the shaders
+    field: u32,
+    range: f32,
+    _padding: [f32; 2],
+}
+
+impl ColorMapUniform {
+    fn new(field: ColorField, range: f32) -> Self {
+        Self {
+            field: field as u32,
+            range,
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+/// A palette as a 1D lookup texture and how to pick a spot on it, for one render mode
+struct ColorMapData {
+    lut: wgpu::Texture,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+impl ColorMapData {
+    fn layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             entries: &[
                 wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
                     ty: wgpu::BindingType::Texture {
                         multisampled: false,
-                        view_dimension: wgpu::TextureViewDimension::D2,
+                        view_dimension: wgpu::TextureViewDimension::D1,
                         sample_type: wgpu::TextureSampleType::Float { filterable: true },
                     },
                     count: None,
@@ -175,54 +579,167 @@ impl SimTextureData {
                     ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
                     count: None,
                 },
-            ],
-            label: Some("sim_texture_bind_group_layout"),
-        });
-        let bind_group = device.create_bind_group(
-            &wgpu::BindGroupDescriptor {
-                layout: &bind_group_layout,
-                entries: &[
-                    wgpu::BindGroupEntry {
-                        binding: 0,
-                        resource: wgpu::BindingResource::TextureView(&sim_texture_view),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
                     },
-                    wgpu::BindGroupEntry {
-                        binding: 1,
-                        resource: wgpu::BindingResource::Sampler(&sim_texture_sampler),
-                    }
-                ],
-                label: Some("sim_texture_bind_group"),
+                    count: None,
+                },
+            ],
+            label: Some("color_map_bind_group_layout"),
+        })
+    }
+
+    /// Starts out with `ColorMap::default_for(mode)`
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue, layout: &wgpu::BindGroupLayout, mode: RenderMode) -> Self {
+        let color_map = ColorMap::default_for(mode);
+        let palettes = palette::built_in();
+        let palette = color_map.find_palette(&palettes).unwrap_or(&palettes[0]);
+        let lut = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                size: wgpu::Extent3d {
+                    width: palette::LUT_SIZE,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D1,
+                // Sampling gives linear colours, like the rest of the shading
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                label: Some("palette lut"),
+                view_formats: &[],
             },
+            wgpu::util::TextureDataOrder::LayerMajor,
+            &palette.lut(),
         );
+        let uniform_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("color map"),
+                contents: bytemuck::bytes_of(&ColorMapUniform::new(color_map.field, color_map.range)),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let lut_view = lut.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&lut_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("color_map_bind_group"),
+        });
         Self {
-            bind_group_layout,
+            lut,
+            uniform_buffer,
             bind_group,
-            texture_size,
-            texture: sim_texture,
         }
     }
+
+    fn write(&self, queue: &wgpu::Queue, palette: &Palette, field: ColorField, range: f32) {
+        queue.write_texture(
+            self.lut.as_image_copy(),
+            &palette.lut(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * palette::LUT_SIZE),
+                rows_per_image: None,
+            },
+            self.lut.size(),
+        );
+        queue.write_buffer(&self.uniform_buffer, 0, bytemuck::bytes_of(&ColorMapUniform::new(field, range)));
+    }
+}
+
+/// Builds pipelines from WGSL source, catching validation errors instead of panicking on them
+fn compile<T>(
+    device: &wgpu::Device,
+    label: &str,
+    source: &str,
+    create_pipelines: impl FnOnce(wgpu::ShaderModule) -> T,
+) -> Result<T, String> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some(label),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let pipelines = create_pipelines(shader);
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => Err(error.to_string()),
+        None => Ok(pipelines),
+    }
 }
 
 struct PipelinePrism {
     pipeline: wgpu::RenderPipeline,
+    /// Kept to rebuild the pipeline with, see `SimRenderer::reload_shader`
+    layout: wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    /// Kept to rebuild the pipeline with, see `SimRenderer::set_sample_count`
+    shader: wgpu::ShaderModule,
+    sample_count: u32,
 
     camera_buffer: wgpu::Buffer,
+    lighting_buffer: wgpu::Buffer,
     camera_bind_group: wgpu::BindGroup,
 
-    depth_texture: texture::Texture,
+    /// Draws the prisms' depth as seen from the main directional light into `shadow_map`
+    shadow_pipeline: wgpu::RenderPipeline,
+    shadow_layout: wgpu::PipelineLayout,
+    shadow_map: texture::Texture,
+    shadow_bind_group: wgpu::BindGroup,
+    /// Set from the lighting, see `SimRenderer::set_lighting`
+    casts_shadows: bool,
+}
+
+/// `CameraUniform` in pipeline_prism.wgsl
+fn camera_uniform(transform: Mat4, position: Vec3) -> [f32; 20] {
+    let mut uniform = [0.0; 20];
+    uniform[..16].copy_from_slice(&transform.to_cols_array());
+    uniform[16..].copy_from_slice(&position.extend(1.0).to_array());
+    uniform
 }
 
 impl PipelinePrism {
     fn new(
         device: &wgpu::Device,
-        surface_config: &wgpu::SurfaceConfiguration,
+        format: wgpu::TextureFormat,
         sim_texture_layout: &wgpu::BindGroupLayout,
+        color_map_layout: &wgpu::BindGroupLayout,
     ) -> Self {
         let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/pipeline_prism.wgsl"));
         let camera_buffer = device.create_buffer_init(
             &wgpu::util::BufferInitDescriptor {
                 label: Some("Projection Matrix"),
-                contents: bytemuck::cast_slice(&Mat4::IDENTITY.to_cols_array()),
+                contents: bytemuck::cast_slice(&camera_uniform(Mat4::IDENTITY, Vec3::ZERO)),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }
+        );
+        let lighting_buffer = device.create_buffer_init(
+            &wgpu::util::BufferInitDescriptor {
+                label: Some("Lighting"),
+                contents: bytemuck::bytes_of(&Lighting::default().uniform(Vec2::ONE, 1.0)),
                 usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             }
         );
@@ -231,14 +748,25 @@ impl PipelinePrism {
             entries: &[
                 wgpu::BindGroupLayoutEntry {
                     binding: 0,
-                    visibility: wgpu::ShaderStages::VERTEX,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
                     ty: wgpu::BindingType::Buffer {
                         ty: wgpu::BufferBindingType::Uniform,
                         has_dynamic_offset: false,
                         min_binding_size: None,
                     },
                     count: None,
-                }
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    // The shadow pass needs the light's view in the vertex shader
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
             label: Some("camera_bind_group_layout"),
         });
@@ -249,32 +777,159 @@ impl PipelinePrism {
                 wgpu::BindGroupEntry {
                     binding: 0,
                     resource: camera_buffer.as_entire_binding(),
-                }
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: lighting_buffer.as_entire_binding(),
+                },
             ],
             label: Some("camera_bind_group"),
         });
+
+        let shadow_map = texture::Texture::create_depth_texture(device, SHADOW_MAP_SIZE, SHADOW_MAP_SIZE, "shadow map");
+        let shadow_map_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Depth,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Comparison),
+                    count: None,
+                },
+            ],
+            label: Some("shadow_map_bind_group_layout"),
+        });
+        let shadow_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &shadow_map_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&shadow_map.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&shadow_map.sampler),
+                },
+            ],
+            label: Some("shadow_map_bind_group"),
+        });
+
         let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("prism render pipeline layout"),
             bind_group_layouts: &[
                 &camera_layout,
                 sim_texture_layout,
+                color_map_layout,
+                &shadow_map_layout,
             ],
             push_constant_ranges: &[],
         });
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        // The shadow map can't be bound while it's being drawn into, so the shadow pass leaves
+        // that group and the colours out
+        let shadow_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("prism shadow pipeline layout"),
+            bind_group_layouts: &[
+                &camera_layout,
+                sim_texture_layout,
+            ],
+            push_constant_ranges: &[],
+        });
+        let pipeline = Self::create_pipeline(device, &render_pipeline_layout, format, 1, &shader);
+        let shadow_pipeline = Self::create_shadow_pipeline(device, &shadow_layout, &shader);
+
+        Self {
+            pipeline,
+            layout: render_pipeline_layout,
+            format,
+            shader,
+            sample_count: 1,
+            camera_buffer,
+            lighting_buffer,
+            camera_bind_group,
+            shadow_pipeline,
+            shadow_layout,
+            shadow_map,
+            shadow_bind_group,
+            casts_shadows: false,
+        }
+    }
+
+    fn create_shadow_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        shader: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Shadow Pipeline"),
+            layout: Some(layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: Some("vs_shadow"),
+                buffers: &[mesh::vertex_desc(), mesh_grid::Instance::desc()],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: None,
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                // Pushes the depth back a little so lit faces don't shadow themselves
+                bias: wgpu::DepthBiasState {
+                    constant: 2,
+                    slope_scale: 2.0,
+                    clamp: 0.0,
+                },
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+            cache: None,
+        })
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        shader: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
+            layout: Some(layout),
             vertex: wgpu::VertexState {
-                module: &shader,
+                module: shader,
                 entry_point: Some("vs_main"),
                 buffers: &[mesh::vertex_desc(), mesh_grid::Instance::desc()],
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
             },
             fragment: Some(wgpu::FragmentState {
-                module: &shader,
+                module: shader,
                 entry_point: Some("fs_main"),
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_config.format,
+                    format,
                     blend: Some(wgpu::BlendState::REPLACE),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
@@ -297,39 +952,54 @@ impl PipelinePrism {
                 bias: wgpu::DepthBiasState::default(),
             }),
             multisample: wgpu::MultisampleState {
-                count: 1,
+                count: sample_count,
                 mask: !0,
                 alpha_to_coverage_enabled: false,
             },
             multiview: None,
             cache: None,
-        });
-
-        let depth_texture = texture::Texture::create_depth_texture(
-            &device, &surface_config, "sim_renderer depth texture",
-        );
-
-        Self {
-            pipeline,
-            camera_buffer,
-            camera_bind_group,
-            depth_texture,
-        }
+        })
     }
 
     fn render(
         &self,
-        view: &wgpu::TextureView,
+        [view, depth_view]: [&wgpu::TextureView; 2],
+        resolve_target: Option<&wgpu::TextureView>,
         encoder: &mut wgpu::CommandEncoder,
-        sim_texture_group: &wgpu::BindGroup,
+        // The sim textures and the colour map, bind groups 1 and 2
+        [sim_texture_group, color_map_group]: [&wgpu::BindGroup; 2],
         prism: &UploadedMesh,
         grid: &UploadedMeshGrid,
     ) {
+        if self.casts_shadows {
+            let mut shadow_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                    view: &self.shadow_map.view,
+                    depth_ops: Some(wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(1.0),
+                        store: wgpu::StoreOp::Store,
+                    }),
+                    stencil_ops: None,
+                }),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+            });
+            shadow_pass.set_pipeline(&self.shadow_pipeline);
+            shadow_pass.set_bind_group(0, &self.camera_bind_group, &[]);
+            shadow_pass.set_bind_group(1, sim_texture_group, &[]);
+            shadow_pass.set_vertex_buffer(0, prism.vertex_buffer.slice(..));
+            shadow_pass.set_vertex_buffer(1, grid.instance_buffer.slice(..));
+            shadow_pass.set_index_buffer(prism.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            shadow_pass.draw_indexed(0..prism.index_count, 0, 0..grid.instance_count);
+        }
+
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("Render Pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                 view: &view,
-                resolve_target: None,
+                resolve_target,
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Clear(wgpu::Color {
                         r: CLEAR_COLOR,
@@ -341,7 +1011,7 @@ impl PipelinePrism {
                 },
             })],
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                view: &self.depth_texture.view,
+                view: depth_view,
                 depth_ops: Some(wgpu::Operations {
                     load: wgpu::LoadOp::Clear(1.0),
                     store: wgpu::StoreOp::Store,
@@ -354,6 +1024,8 @@ impl PipelinePrism {
         render_pass.set_pipeline(&self.pipeline);
         render_pass.set_bind_group(0, &self.camera_bind_group, &[]);
         render_pass.set_bind_group(1, sim_texture_group, &[]);
+        render_pass.set_bind_group(2, color_map_group, &[]);
+        render_pass.set_bind_group(3, &self.shadow_bind_group, &[]);
         render_pass.set_vertex_buffer(0, prism.vertex_buffer.slice(..));
         render_pass.set_vertex_buffer(1, grid.instance_buffer.slice(..));
         render_pass.set_index_buffer(prism.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
@@ -363,36 +1035,56 @@ impl PipelinePrism {
 
 struct Pipeline2D {
     pipeline: wgpu::RenderPipeline,
+    /// Kept to rebuild the pipeline with, see `SimRenderer::reload_shader`
+    layout: wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
 }
 
 impl Pipeline2D {
     fn new(
         device: &wgpu::Device,
-        surface_config: &wgpu::SurfaceConfiguration,
+        format: wgpu::TextureFormat,
         sim_texture_layout: &wgpu::BindGroupLayout,
+        color_map_layout: &wgpu::BindGroupLayout,
     ) -> Self {
         let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/pipeline_2d.wgsl"));
         let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
             label: Some("Pipeline 2D Layout"),
             bind_group_layouts: &[
                 sim_texture_layout,
+                color_map_layout,
             ],
             push_constant_ranges: &[],
         });
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        let pipeline = Self::create_pipeline(device, &render_pipeline_layout, format, &shader);
+
+        Self {
+            pipeline,
+            layout: render_pipeline_layout,
+            format,
+        }
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        layout: &wgpu::PipelineLayout,
+        format: wgpu::TextureFormat,
+        shader: &wgpu::ShaderModule,
+    ) -> wgpu::RenderPipeline {
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
             label: Some("2D Render Pipeline"),
-            layout: Some(&render_pipeline_layout),
+            layout: Some(layout),
             vertex: wgpu::VertexState {
-                module: &shader,
+                module: shader,
                 entry_point: Some("vs_main"),
                 buffers: &[],
                 compilation_options: wgpu::PipelineCompilationOptions::default(),
             },
             fragment: Some(wgpu::FragmentState {
-                module: &shader,
+                module: shader,
                 entry_point: Some("fs_main"),
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: surface_config.format,
+                    format,
                     blend: Some(wgpu::BlendState::REPLACE),
                     write_mask: wgpu::ColorWrites::ALL,
                 })],
@@ -415,11 +1107,7 @@ impl Pipeline2D {
             },
             multiview: None,
             cache: None,
-        });
-
-        Self {
-            pipeline
-        }
+        })
     }
 
     fn render(
@@ -427,6 +1115,7 @@ impl Pipeline2D {
         view: &wgpu::TextureView,
         encoder: &mut wgpu::CommandEncoder,
         sim_texture_bind_group: &wgpu::BindGroup,
+        color_map_bind_group: &wgpu::BindGroup,
     ) {
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: Some("2d render pass"),
@@ -449,6 +1138,127 @@ impl Pipeline2D {
         });
         render_pass.set_pipeline(&self.pipeline);
         render_pass.set_bind_group(0, sim_texture_bind_group, &[]);
+        render_pass.set_bind_group(1, color_map_bind_group, &[]);
         render_pass.draw(0..4, 0..1);
     }
 }
+
+/// A full screen pass reading an image already drawn, e.g. to smooth its edges
+struct PipelinePost {
+    pipeline: wgpu::RenderPipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+}
+
+impl PipelinePost {
+    /// `fragment` is the entry point in post.wgsl to run
+    fn new(device: &wgpu::Device, format: wgpu::TextureFormat, fragment: &str) -> Self {
+        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/post.wgsl"));
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+            label: Some("post_bind_group_layout"),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("post pipeline layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(fragment),
+            layout: Some(&layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: Some("vs_main"),
+                buffers: &[],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: Some(fragment),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::REPLACE),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+            cache: None,
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("post sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self {
+            pipeline,
+            bind_group_layout,
+            sampler,
+        }
+    }
+
+    /// Draws `source` into `view` through the fragment shader. Both have to be the same size.
+    fn render(
+        &self,
+        device: &wgpu::Device,
+        source: &wgpu::TextureView,
+        view: &wgpu::TextureView,
+        encoder: &mut wgpu::CommandEncoder,
+    ) {
+        // The source changes whenever the target is resized, binding it each frame is cheap
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+            ],
+            label: Some("post_bind_group"),
+        });
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Post Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+        });
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, &bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
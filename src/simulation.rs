@@ -1,7 +1,27 @@
 use bytemuck::{Pod, Zeroable};
-use macaw::{Vec2};
+use macaw::{UVec2, uvec2, Vec2, vec2};
+use crate::brush::Brush;
+use crate::poke::Poke;
+use crate::solver::{self, SimParams, Source, SolverKind, SolverOutput, WaveSolver};
 
-pub const DIVISIONS: u32 = 128;
+pub const DEFAULT_SIZE: UVec2 = uvec2(128, 128);
+/// Narrowest and shortest grid the settings window goes down to
+pub const MIN_SIZE: u32 = 1;
+/// Widest and tallest grid the settings window goes up to
+pub const MAX_SIZE: u32 = 2048;
+
+/// Whether a grid of `size` fits within the limits files are held to
+pub fn is_valid_size(size: UVec2) -> bool {
+    size.min_element() >= MIN_SIZE && size.max_element() <= MAX_SIZE
+}
+
+/// `is_valid_size` with an error to show when it isn't
+pub fn check_size(size: UVec2) -> Result<(), String> {
+    if !is_valid_size(size) {
+        return Err(format!("grid size {}x{} isn't between {} and {}", size.x, size.y, MIN_SIZE, MAX_SIZE));
+    }
+    Ok(())
+}
 
 #[repr(C)]
 #[derive(Clone, Copy, Pod, Zeroable)]
@@ -28,71 +48,53 @@ impl Default for WavePoint {
 }
 
 pub struct WaveSimulation {
-    divisions: usize,
-    pub damping: f32,
+    width: usize,
+    height: usize,
+    params: SimParams,
+    sources: Vec<Source>,
     current_state: Vec<WavePoint>,
     previous_state: Vec<WavePoint>,
 }
 
 impl WaveSimulation {
-    pub fn new(divisions: u32) -> Self {
-        let divisions = divisions as usize;
-         Self {
-            divisions,
-            damping: 0.98,
-            current_state: vec![WavePoint::default(); divisions * divisions],
-            previous_state: vec![WavePoint::default(); divisions * divisions],
+    pub fn new(size: UVec2) -> Self {
+        let width = size.x as usize;
+        let height = size.y as usize;
+        Self {
+            width,
+            height,
+            params: SimParams::default(),
+            sources: Vec::new(),
+            current_state: vec![WavePoint::default(); width * height],
+            previous_state: vec![WavePoint::default(); width * height],
         }
     }
 
-    pub fn poke_normalized(&mut self, point: Vec2) {
-        let clamped = point.clamp(Vec2::ZERO, Vec2::ONE);
-        let x = clamped.x * self.divisions as f32;
-        let y = clamped.y * self.divisions as f32;
-        self.poke(x as usize, y as usize);
-    }
-
-    pub fn poke(&mut self, x_mid: usize, y_mid: usize) {
-        let x_start = x_mid.max(2) - 2;
-        let y_start = y_mid.max(2) - 2;
-        for y in y_start..(y_start + 5).min(self.divisions) {
-            for x in x_start..(x_start + 5).min(self.divisions){
-                let index = y * self.divisions + x;
-                if index < self.current_state.len() {
-                    self.current_state[index].value += 1.0;
-                }
-            }
-        }
+    pub fn poke(&mut self, poke: &Poke, center: Vec2) {
+        let size = self.size();
+        poke.apply(&mut self.current_state, size, center);
     }
 
     pub fn advance(&mut self) {
         std::mem::swap(&mut self.current_state, &mut self.previous_state);
-        for y in 0..self.divisions {
-            for x in 0..self.divisions {
-                let index = y * self.divisions + x;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let index = y * self.width + x;
                 if self.previous_state[index].medium >= 0.0 {
                     let value = self.previous_state[index].value;
                     let vel = self.previous_state[index].velocity;
                     let mut target = self.previous_state[index].medium;
 
-                    let mut mid = 0.0;
-                    if x != 0 {
-                        mid += self.get_value(x - 1, y);
-                    }
-                    if x != self.divisions - 1 {
-                        mid += self.get_value(x + 1, y);
-                    }
-                    if y != 0 {
-                        mid += self.get_value(x, y - 1)
-                    }
-                    if y != self.divisions - 1 {
-                        mid += self.get_value(x, y + 1)
-                    }
-                    mid /= 4.0;
+                    let mid = self.neighbour_value(x, y, -1, 0)
+                        + self.neighbour_value(x, y, 1, 0)
+                        + self.neighbour_value(x, y, 0, -1)
+                        + self.neighbour_value(x, y, 0, 1);
+                    let mid = mid / 4.0;
 
                     target *= 1.5;
-                    let new_vel = target * (mid - value) + vel * self.damping;
-                    let new_value = value + new_vel;
+                    let absorption = self.params.boundaries.absorption(x, y, self.width, self.height);
+                    let new_vel = (target * (mid - value) + vel * self.params.damping) * absorption;
+                    let new_value = (value + new_vel) * absorption;
 
                     self.current_state[index].value = new_value;
                     self.current_state[index].velocity = new_vel;
@@ -102,16 +104,234 @@ impl WaveSimulation {
                 }
             }
         }
+        self.apply_sources();
+    }
+
+    /// Holds the cells under each source at its value, overriding what `advance` worked out
+    fn apply_sources(&mut self) {
+        let max = vec2(self.width as f32, self.height as f32);
+        for source in &self.sources {
+            let min_corner = (source.start.min(source.end) - source.radius).floor().clamp(Vec2::ZERO, max);
+            let max_corner = (source.start.max(source.end) + source.radius).ceil().clamp(Vec2::ZERO, max);
+            for y in min_corner.y as usize..max_corner.y as usize {
+                for x in min_corner.x as usize..max_corner.x as usize {
+                    let index = y * self.width + x;
+                    let previous = self.previous_state[index];
+                    if previous.medium < 0.0 || !source.covers(vec2(x as f32 + 0.5, y as f32 + 0.5)) {
+                        continue;
+                    }
+                    self.current_state[index].value = source.value;
+                    self.current_state[index].velocity = source.value - previous.value;
+                }
+            }
+        }
+    }
+
+    fn neighbour_value(&self, x: usize, y: usize, dx: isize, dy: isize) -> f32 {
+        match self.params.boundaries.neighbour(x, y, dx, dy, self.width, self.height) {
+            Some((x, y)) => self.get_value(x, y),
+            None => 0.0,
+        }
     }
 
     fn get_value(&self, x: usize, y: usize) -> f32 {
-        self.previous_state[y * self.divisions + x].value.max(0.0)
+        self.previous_state[y * self.width + x].value
+    }
+
+    pub fn size(&self) -> UVec2 {
+        uvec2(self.width as u32, self.height as u32)
     }
 
-    pub fn current_state(&self) -> (u32, &[u8]) {
+    pub fn current_state(&self) -> (UVec2, &[u8]) {
         (
-            self.divisions as u32,
+            self.size(),
             bytemuck::cast_slice(self.current_state.as_slice())
         )
     }
-}
\ No newline at end of file
+
+    /// State as of the tick before the last `advance`
+    pub fn previous_state(&self) -> &[u8] {
+        bytemuck::cast_slice(self.previous_state.as_slice())
+    }
+}
+
+impl WaveSolver for WaveSimulation {
+    fn kind(&self) -> SolverKind {
+        SolverKind::Cpu
+    }
+
+    fn size(&self) -> UVec2 {
+        WaveSimulation::size(self)
+    }
+
+    fn params(&self) -> &SimParams {
+        &self.params
+    }
+
+    fn params_mut(&mut self) -> &mut SimParams {
+        &mut self.params
+    }
+
+    fn advance(&mut self) {
+        WaveSimulation::advance(self)
+    }
+
+    fn set_sources(&mut self, sources: &[Source]) {
+        self.sources = sources.iter().take(solver::MAX_SOURCES).copied().collect();
+    }
+
+    fn poke(&mut self, poke: &Poke, center: Vec2) {
+        WaveSimulation::poke(self, poke, center)
+    }
+
+    fn reset(&mut self) {
+        for point in self.current_state.iter_mut().chain(self.previous_state.iter_mut()) {
+            point.value = 0.0;
+            point.velocity = 0.0;
+        }
+    }
+
+    fn paint(&mut self, brush: &Brush, center: Vec2) {
+        // Both buffers carry the medium, see import_points
+        let size = self.size();
+        brush.paint(&mut self.current_state, size, center);
+        brush.paint(&mut self.previous_state, size, center);
+    }
+
+    fn resize(&mut self, size: UVec2) {
+        let points = resample(&self.current_state, self.size(), size);
+        *self = Self {
+            params: self.params,
+            sources: std::mem::take(&mut self.sources),
+            ..Self::new(size)
+        };
+        self.import_points(&points);
+    }
+
+    fn export_points(&self) -> Vec<WavePoint> {
+        self.current_state.clone()
+    }
+
+    fn import_points(&mut self, points: &[WavePoint]) {
+        self.current_state.copy_from_slice(points);
+        // advance only writes value and velocity, so both buffers need the same medium
+        self.previous_state.copy_from_slice(points);
+    }
+
+    fn export_previous_points(&self) -> Vec<WavePoint> {
+        self.previous_state.clone()
+    }
+
+    fn import_state(&mut self, current: &[WavePoint], previous: &[WavePoint]) {
+        self.current_state.copy_from_slice(current);
+        self.previous_state.copy_from_slice(previous);
+    }
+
+    fn output(&self) -> SolverOutput<'_> {
+        let (size, current) = self.current_state();
+        SolverOutput::Points { size, current, previous: self.previous_state() }
+    }
+}
+
+/// Stretches a field onto a grid of a different size. Height and velocity are interpolated,
+/// the medium is not so walls keep a hard edge.
+pub fn resample(points: &[WavePoint], from: UVec2, to: UVec2) -> Vec<WavePoint> {
+    let from_width = from.x as usize;
+    let max = from.as_vec2() - 1.0;
+    let scale = from.as_vec2() / to.as_vec2();
+    let sample = |x: usize, y: usize| points[y * from_width + x];
+
+    let mut resampled = Vec::with_capacity((to.x * to.y) as usize);
+    for y in 0..to.y {
+        for x in 0..to.x {
+            // Line up cell centers rather than corners so the field doesn't drift
+            let source = ((vec2(x as f32, y as f32) + 0.5) * scale - 0.5).clamp(Vec2::ZERO, max);
+            let x0 = source.x.floor() as usize;
+            let y0 = source.y.floor() as usize;
+            let x1 = (x0 + 1).min(from.x as usize - 1);
+            let y1 = (y0 + 1).min(from.y as usize - 1);
+            let t = source - vec2(x0 as f32, y0 as f32);
+            let lerp = |field: fn(&WavePoint) -> f32| {
+                let top = field(&sample(x0, y0)) * (1.0 - t.x) + field(&sample(x1, y0)) * t.x;
+                let bottom = field(&sample(x0, y1)) * (1.0 - t.x) + field(&sample(x1, y1)) * t.x;
+                top * (1.0 - t.y) + bottom * t.y
+            };
+            let nearest = sample(source.x.round() as usize, source.y.round() as usize);
+            resampled.push(WavePoint {
+                value: lerp(|point| point.value),
+                velocity: lerp(|point| point.velocity),
+                ..nearest
+            });
+        }
+    }
+    resampled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brush;
+
+    fn field(size: UVec2, value: impl Fn(u32, u32) -> f32) -> Vec<WavePoint> {
+        (0..size.y)
+            .flat_map(|y| (0..size.x).map(move |x| (x, y)))
+            .map(|(x, y)| WavePoint { value: value(x, y), ..WavePoint::default() })
+            .collect()
+    }
+
+    #[test]
+    fn sizes_are_held_to_the_limits() {
+        assert!(is_valid_size(uvec2(MIN_SIZE, MAX_SIZE)));
+        assert!(!is_valid_size(uvec2(0, 8)));
+        assert!(!is_valid_size(uvec2(8, MAX_SIZE + 1)));
+        assert!(check_size(uvec2(MAX_SIZE + 1, 8)).is_err());
+    }
+
+    #[test]
+    fn resample_to_the_same_size_is_a_copy() {
+        let size = uvec2(5, 3);
+        let points = field(size, |x, y| (x * 10 + y) as f32);
+        let resampled = resample(&points, size, size);
+        assert_eq!(bytemuck::cast_slice::<_, u8>(&resampled), bytemuck::cast_slice::<_, u8>(&points));
+    }
+
+    #[test]
+    fn resample_keeps_a_flat_field_flat() {
+        let points = field(uvec2(4, 4), |_, _| 0.5);
+        let resampled = resample(&points, uvec2(4, 4), uvec2(7, 9));
+        assert_eq!(resampled.len(), 63);
+        assert!(resampled.iter().all(|point| (point.value - 0.5).abs() < 1e-6));
+    }
+
+    #[test]
+    fn resample_interpolates_height_but_not_walls() {
+        let mut points = field(uvec2(2, 1), |x, _| x as f32);
+        points[1].medium = brush::WALL;
+        let resampled = resample(&points, uvec2(2, 1), uvec2(4, 1));
+        let values: Vec<f32> = resampled.iter().map(|point| point.value).collect();
+        assert_eq!(values, [0.0, 0.25, 0.75, 1.0]);
+        let media: Vec<f32> = resampled.iter().map(|point| point.medium).collect();
+        assert_eq!(media, [1.0, 1.0, brush::WALL, brush::WALL]);
+    }
+
+    #[test]
+    fn sources_hold_their_cells() {
+        let mut simulation = WaveSimulation::new(uvec2(8, 8));
+        let source = Source { start: vec2(4.0, 4.0), end: vec2(4.0, 4.0), radius: 1.0, value: 0.75 };
+        simulation.set_sources(&[source]);
+        simulation.advance();
+        let points = simulation.export_points();
+        assert_eq!(points[4 * 8 + 4].value, 0.75);
+        assert_eq!(points[0].value, 0.0);
+    }
+
+    #[test]
+    fn walls_stay_flat() {
+        let mut simulation = WaveSimulation::new(uvec2(8, 8));
+        let mut points = field(uvec2(8, 8), |_, _| 1.0);
+        points[9].medium = brush::WALL;
+        simulation.import_points(&points);
+        simulation.advance();
+        assert_eq!(simulation.export_points()[9].value, 0.0);
+    }
+}
@@ -0,0 +1,218 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use macaw::{UVec2, uvec2};
+use crate::boundary::Boundaries;
+use crate::simulation::{self, WavePoint};
+use crate::solver::{SimParams, WaveSolver};
+
+const MAGIC: &[u8; 4] = b"RIPL";
+/// Bump whenever the layout below changes, older versions can then be upgraded in `read`
+pub const VERSION: u32 = 1;
+
+/// Everything needed to pick a simulation back up exactly where it was left.
+///
+/// On disk everything is little endian, in this order:
+///
+/// - `RIPL` magic bytes
+/// - version, `u32`
+/// - width and height, `u32` each
+/// - damping, `f32`
+/// - sponge thickness, `u32`
+/// - left, right, top and bottom edge modes, `u32` each, see `EdgeMode`
+/// - tick, `u64`
+/// - the current then the previous field, row by row, each cell as value, velocity, medium and
+///   extra `f32`s
+pub struct Snapshot {
+    pub size: UVec2,
+    pub params: SimParams,
+    /// Ticks run when the snapshot was taken, so emitters carry on in phase
+    pub tick: u64,
+    pub current: Vec<WavePoint>,
+    pub previous: Vec<WavePoint>,
+}
+
+impl Snapshot {
+    pub fn capture(solver: &dyn WaveSolver, tick: u64) -> Self {
+        Self {
+            size: solver.size(),
+            params: *solver.params(),
+            tick,
+            current: solver.export_points(),
+            previous: solver.export_previous_points(),
+        }
+    }
+
+    /// Loads the snapshot into `solver`, which has to be resized to `size` first
+    pub fn restore(&self, solver: &mut dyn WaveSolver) {
+        assert_eq!(solver.size(), self.size, "solver has to be resized before restoring a snapshot");
+        *solver.params_mut() = self.params;
+        solver.import_state(&self.current, &self.previous);
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+        writer.write_all(&self.size.x.to_le_bytes())?;
+        writer.write_all(&self.size.y.to_le_bytes())?;
+        writer.write_all(&self.params.damping.to_le_bytes())?;
+        writer.write_all(&self.params.boundaries.sponge_thickness.to_le_bytes())?;
+        for mode in self.params.boundaries.modes() {
+            writer.write_all(&mode.to_le_bytes())?;
+        }
+        writer.write_all(&self.tick.to_le_bytes())?;
+        for point in self.current.iter().chain(&self.previous) {
+            for channel in [point.value, point.velocity, point.medium, point.extra] {
+                writer.write_all(&channel.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn read(reader: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a ripple snapshot"));
+        }
+        let version = read_u32(reader)?;
+        if version != VERSION {
+            return Err(invalid_data(format!("unsupported snapshot version {}, expected {}", version, VERSION)));
+        }
+        let size = uvec2(read_u32(reader)?, read_u32(reader)?);
+        // Held to what the app can simulate, so a corrupt header can't ask for gigabytes
+        if !simulation::is_valid_size(size) {
+            return Err(invalid_data(format!("bad grid size {}x{}", size.x, size.y)));
+        }
+        let damping = read_f32(reader)?;
+        let sponge_thickness = read_u32(reader)?;
+        let mut modes = [0; 4];
+        for mode in modes.iter_mut() {
+            *mode = read_u32(reader)?;
+        }
+        let boundaries = Boundaries::from_modes(modes, sponge_thickness)
+            .ok_or_else(|| invalid_data("bad edge mode"))?;
+        let mut tick = [0; 8];
+        reader.read_exact(&mut tick)?;
+
+        let count = (size.x * size.y) as usize;
+        let current = read_points(reader, count)?;
+        let previous = read_points(reader, count)?;
+        Ok(Self {
+            size,
+            params: SimParams { damping, boundaries },
+            tick: u64::from_le_bytes(tick),
+            current,
+            previous,
+        })
+    }
+}
+
+fn read_points(reader: &mut impl Read, count: usize) -> io::Result<Vec<WavePoint>> {
+    let mut points = Vec::with_capacity(count);
+    for _ in 0..count {
+        points.push(WavePoint {
+            value: read_f32(reader)?,
+            velocity: read_f32(reader)?,
+            medium: read_f32(reader)?,
+            extra: read_f32(reader)?,
+        });
+    }
+    Ok(points)
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_f32(reader: &mut impl Read) -> io::Result<f32> {
+    read_u32(reader).map(f32::from_bits)
+}
+
+fn invalid_data(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boundary::{Edge, EdgeMode};
+
+    fn snapshot() -> Snapshot {
+        let size = uvec2(3, 2);
+        let points = |offset: f32| (0..size.x * size.y)
+            .map(|i| WavePoint {
+                value: i as f32 + offset,
+                velocity: -(i as f32),
+                medium: if i == 4 { -1.0 } else { 1.0 },
+                extra: 0.0,
+            })
+            .collect();
+        let mut params = SimParams { damping: 0.97, ..SimParams::default() };
+        params.boundaries.set(Edge::Top, EdgeMode::Absorbing);
+        params.boundaries.sponge_thickness = 12;
+        Snapshot {
+            size,
+            params,
+            tick: 1234,
+            current: points(0.5),
+            previous: points(0.25),
+        }
+    }
+
+    fn bytes(points: &[WavePoint]) -> &[u8] {
+        bytemuck::cast_slice(points)
+    }
+
+    #[test]
+    fn round_trip() {
+        let snapshot = snapshot();
+        let mut data = Vec::new();
+        snapshot.write(&mut data).unwrap();
+        let read = Snapshot::read(&mut data.as_slice()).unwrap();
+        assert_eq!(read.size, snapshot.size);
+        assert_eq!(read.params, snapshot.params);
+        assert_eq!(read.tick, snapshot.tick);
+        assert_eq!(bytes(&read.current), bytes(&snapshot.current));
+        assert_eq!(bytes(&read.previous), bytes(&snapshot.previous));
+    }
+
+    #[test]
+    fn rejects_truncated_data() {
+        let mut data = Vec::new();
+        snapshot().write(&mut data).unwrap();
+        data.truncate(data.len() - 1);
+        let error = Snapshot::read(&mut data.as_slice()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut data = Vec::new();
+        snapshot().write(&mut data).unwrap();
+        data[0] = b'X';
+        let error = Snapshot::read(&mut data.as_slice()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_oversized_grid() {
+        let mut data = Vec::new();
+        snapshot().write(&mut data).unwrap();
+        data[8..12].copy_from_slice(&(simulation::MAX_SIZE + 1).to_le_bytes());
+        let error = Snapshot::read(&mut data.as_slice()).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,213 @@
+use std::sync::Arc;
+use macaw::{UVec2, Vec2};
+use serde::{Deserialize, Serialize};
+use crate::boundary::Boundaries;
+use crate::brush::{self, Brush};
+use crate::gpu_simulation::GpuWaveSimulation;
+use crate::poke::Poke;
+use crate::simulation::{WavePoint, WaveSimulation};
+
+/// Tunables shared by every solver, edited from the settings window
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SimParams {
+    pub damping: f32,
+    pub boundaries: Boundaries,
+}
+
+impl Default for SimParams {
+    fn default() -> Self {
+        Self {
+            damping: 0.98,
+            boundaries: Boundaries::default(),
+        }
+    }
+}
+
+/// Most sources a solver has to handle at once, has to match MAX_SOURCES in simulation.wgsl
+pub const MAX_SOURCES: usize = 16;
+
+/// Cells held at a set height while the solver advances, see `emitter::Emitter`
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Source {
+    /// In cells. A point source has `start == end`.
+    pub start: Vec2,
+    pub end: Vec2,
+    pub radius: f32,
+    pub value: f32,
+}
+
+impl Source {
+    /// Whether the cell with its center at `point` is within `radius` of the segment
+    pub fn covers(&self, point: Vec2) -> bool {
+        let segment = self.end - self.start;
+        let t = if segment.length_squared() > 0.0 {
+            ((point - self.start).dot(segment) / segment.length_squared()).clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        point.distance(self.start + segment * t) <= self.radius
+    }
+}
+
+/// What the renderer needs to draw the field: the latest state and the one from the tick before,
+/// so frames can be drawn in between
+pub enum SolverOutput<'a> {
+    /// `WavePoint`s in CPU memory that still have to be uploaded
+    Points { size: UVec2, current: &'a [u8], previous: &'a [u8] },
+    /// Already on the GPU, laid out the same as the renderer's sim texture
+    Texture { current: &'a wgpu::Texture, previous: &'a wgpu::Texture },
+}
+
+/// A way of stepping the wave field. Implement this to try out a new update rule without
+/// touching the rest of the app, then add it to `SolverKind`.
+pub trait WaveSolver {
+    fn kind(&self) -> SolverKind;
+
+    /// Width and height of the grid in cells
+    fn size(&self) -> UVec2;
+
+    fn params(&self) -> &SimParams;
+
+    fn params_mut(&mut self) -> &mut SimParams;
+
+    fn advance(&mut self);
+
+    /// Replaces the sources applied on every following `advance`. Only the first `MAX_SOURCES`
+    /// are used.
+    fn set_sources(&mut self, sources: &[Source]);
+
+    /// Adds `poke` to the field around `center`, given in cells
+    fn poke(&mut self, poke: &Poke, center: Vec2);
+
+    /// Calms the water back down, keeping the medium as is
+    fn reset(&mut self);
+
+    /// Paints walls or medium around `center`, given in cells
+    fn paint(&mut self, brush: &Brush, center: Vec2) {
+        let mut points = self.export_points();
+        brush.paint(&mut points, self.size(), center);
+        self.import_points(&points);
+    }
+
+    /// Removes all walls and painted medium
+    fn clear_medium(&mut self) {
+        let mut points = self.export_points();
+        brush::clear_medium(&mut points);
+        self.import_points(&points);
+    }
+
+    /// Moves to a grid of the new size, resampling the current field onto it
+    fn resize(&mut self, size: UVec2);
+
+    /// Copies the whole field out, row by row. May stall on GPU solvers.
+    fn export_points(&self) -> Vec<WavePoint>;
+
+    /// Replaces the whole field. `points` has to match the current grid size.
+    fn import_points(&mut self, points: &[WavePoint]);
+
+    /// The field as it was before the last `advance`
+    fn export_previous_points(&self) -> Vec<WavePoint>;
+
+    /// Replaces both the latest and the previous field, e.g. when loading a snapshot
+    fn import_state(&mut self, current: &[WavePoint], previous: &[WavePoint]);
+
+    fn output(&self) -> SolverOutput<'_>;
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum SolverKind {
+    /// The original update rule, run on the CPU. Used as the reference for every other solver.
+    Cpu,
+    /// Same rule as `Cpu` in a compute shader
+    Gpu,
+}
+
+impl SolverKind {
+    pub const ALL: [SolverKind; 2] = [SolverKind::Cpu, SolverKind::Gpu];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SolverKind::Cpu => "CPU",
+            SolverKind::Gpu => "GPU",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn create(
+        &self,
+        device: &Arc<wgpu::Device>,
+        queue: &Arc<wgpu::Queue>,
+        size: UVec2,
+    ) -> Box<dyn WaveSolver> {
+        match self {
+            SolverKind::Cpu => Box::new(WaveSimulation::new(size)),
+            SolverKind::Gpu => Box::new(GpuWaveSimulation::new(device.clone(), queue.clone(), size)),
+        }
+    }
+}
+
+/// Builds a solver of the given kind that picks up where `solver` left off
+pub fn switch_solver(
+    solver: &dyn WaveSolver,
+    kind: SolverKind,
+    device: &Arc<wgpu::Device>,
+    queue: &Arc<wgpu::Queue>,
+) -> Box<dyn WaveSolver> {
+    let mut next = kind.create(device, queue, solver.size());
+    *next.params_mut() = *solver.params();
+    next.import_points(&solver.export_points());
+    next
+}
+
+/// Runs `solver` and the CPU reference from the same starting field and returns the largest
+/// difference in height after `ticks` steps.
+pub fn compare_with_reference(
+    solver: &dyn WaveSolver,
+    ticks: u32,
+    device: &Arc<wgpu::Device>,
+    queue: &Arc<wgpu::Queue>,
+) -> f32 {
+    let mut reference = switch_solver(solver, SolverKind::Cpu, device, queue);
+    let mut candidate = switch_solver(solver, solver.kind(), device, queue);
+    for _ in 0..ticks {
+        reference.advance();
+        candidate.advance();
+    }
+    max_difference(&reference.export_points(), &candidate.export_points())
+}
+
+/// Largest difference in height between two fields of the same size
+fn max_difference(reference: &[WavePoint], candidate: &[WavePoint]) -> f32 {
+    reference.iter()
+        .zip(candidate)
+        .map(|(a, b)| (a.value - b.value).abs())
+        .fold(0.0, f32::max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(values: &[f32]) -> Vec<WavePoint> {
+        values.iter().map(|&value| WavePoint { value, ..WavePoint::default() }).collect()
+    }
+
+    #[test]
+    fn difference_is_the_largest_height_gap() {
+        assert_eq!(max_difference(&points(&[0.0, 1.0, -1.0]), &points(&[0.25, 1.0, 0.5])), 1.5);
+        assert_eq!(max_difference(&points(&[0.5, 0.5]), &points(&[0.5, 0.5])), 0.0);
+    }
+
+    #[test]
+    fn sources_cover_a_capsule() {
+        let source = Source { start: Vec2::new(0.0, 0.0), end: Vec2::new(4.0, 0.0), radius: 1.0, value: 1.0 };
+        assert!(source.covers(Vec2::new(2.0, 1.0)));
+        assert!(source.covers(Vec2::new(-1.0, 0.0)));
+        assert!(!source.covers(Vec2::new(5.0, 1.0)));
+        assert!(!source.covers(Vec2::new(2.0, 1.5)));
+    }
+}
@@ -0,0 +1,102 @@
+use std::time::Instant;
+use macaw::Vec2;
+
+/// Cursor speed in cells per second that pokes at the poke's own amplitude
+const REFERENCE_SPEED: f32 = 100.0;
+/// Caps how hard a quick flick can hit the water
+const MAX_SCALE: f32 = 4.0;
+
+/// Turns the cursor samples of a drag into evenly spaced pokes along its path
+#[derive(Default)]
+pub struct Stroke {
+    last: Option<(Vec2, Instant)>,
+}
+
+impl Stroke {
+    pub fn is_active(&self) -> bool {
+        self.last.is_some()
+    }
+
+    /// Starts a new stroke at `position`, given in cells
+    pub fn begin(&mut self, position: Vec2) {
+        self.last = Some((position, Instant::now()));
+    }
+
+    pub fn end(&mut self) {
+        self.last = None;
+    }
+
+    /// Moves the stroke on to `position` and returns where to poke along the way, each with how
+    /// much to scale the poke by for the cursor speed. `spacing` is the distance between pokes
+    /// in cells. Leftover distance carries over to the next call so slow drags still poke.
+    pub fn extend(&mut self, position: Vec2, spacing: f32) -> Vec<(Vec2, f32)> {
+        let Some((last, last_time)) = self.last else {
+            return Vec::new();
+        };
+        let distance = last.distance(position);
+        let spacing = spacing.max(1.0);
+        if distance < spacing {
+            return Vec::new();
+        }
+        let now = Instant::now();
+        let seconds = (now - last_time).as_secs_f32().max(0.001);
+        let scale = (distance / seconds / REFERENCE_SPEED).min(MAX_SCALE);
+
+        let steps = (distance / spacing).floor() as usize;
+        let direction = (position - last) / distance;
+        let points = (1..=steps)
+            .map(|step| (last + direction * spacing * step as f32, scale))
+            .collect();
+        self.last = Some((last + direction * spacing * steps as f32, now));
+        points
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use macaw::vec2;
+
+    fn centers(points: Vec<(Vec2, f32)>) -> Vec<Vec2> {
+        points.into_iter().map(|(center, _)| center).collect()
+    }
+
+    #[test]
+    fn nothing_before_the_stroke_begins() {
+        let mut stroke = Stroke::default();
+        assert!(stroke.extend(vec2(10.0, 0.0), 2.0).is_empty());
+        assert!(!stroke.is_active());
+    }
+
+    #[test]
+    fn pokes_are_evenly_spaced() {
+        let mut stroke = Stroke::default();
+        stroke.begin(vec2(0.0, 0.0));
+        let points = centers(stroke.extend(vec2(7.0, 0.0), 2.0));
+        assert_eq!(points, [vec2(2.0, 0.0), vec2(4.0, 0.0), vec2(6.0, 0.0)]);
+    }
+
+    #[test]
+    fn leftover_distance_carries_over() {
+        let mut stroke = Stroke::default();
+        stroke.begin(vec2(0.0, 0.0));
+        assert!(stroke.extend(vec2(0.0, 1.5), 2.0).is_empty());
+        assert_eq!(centers(stroke.extend(vec2(0.0, 3.0), 2.0)), [vec2(0.0, 2.0)]);
+        assert_eq!(centers(stroke.extend(vec2(0.0, 4.0), 2.0)), [vec2(0.0, 4.0)]);
+    }
+
+    #[test]
+    fn spacing_is_at_least_a_cell() {
+        let mut stroke = Stroke::default();
+        stroke.begin(vec2(0.0, 0.0));
+        assert_eq!(stroke.extend(vec2(3.0, 0.0), 0.1).len(), 3);
+    }
+
+    #[test]
+    fn ending_stops_the_stroke() {
+        let mut stroke = Stroke::default();
+        stroke.begin(vec2(0.0, 0.0));
+        stroke.end();
+        assert!(stroke.extend(vec2(10.0, 0.0), 2.0).is_empty());
+    }
+}